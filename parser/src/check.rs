@@ -0,0 +1,762 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::diagnostic::Diagnostic;
+use crate::token::unescape;
+use crate::token::BiOp;
+use crate::token::Node;
+use crate::token::Span;
+use crate::token::Token;
+
+/// Maximum number of characters `input()` reads.
+pub(crate) const LINE_SIZE: usize = 64;
+
+/// Checks that every value in the program is used the way its type allows, before any
+/// code is generated. Returns all errors and warnings found.
+pub fn check(root: &Node) -> Vec<Diagnostic> {
+    let mut c = Checker {
+        env:         HashMap::new(),
+        scopes:      vec![Vec::new()],
+        consts:      HashMap::new(),
+        funcs:       HashMap::new(),
+        func:        None,
+        diagnostics: Vec::new(),
+    };
+    // functions and constants are hoisted, so they can be used before their definition
+    for n in &root.children {
+        match (&n.token, n.children.first()) {
+            (Token::FuncSig, Some(Node { token: Token::Ident(name), span, .. })) => match c.funcs.get(name) {
+                Some(&(_, first)) => c.diagnostics.push(
+                    Diagnostic::error(*span, format!("the function `{}` is already defined", name))
+                        .with_note(format!("the first definition is at {}", first)),
+                ),
+                None => {
+                    c.funcs.insert(name.clone(), (n.children[1].children.len(), *span));
+                },
+            },
+            (Token::ConstSig, Some(Node { token: Token::Ident(name), span, .. })) => {
+                c.check_const(name, *span, &n.children[1]);
+            },
+            _ => (),
+        }
+    }
+    c.check_statements(&root.children, true);
+    // hoisted constants are checked first, but reported in the order they appear in
+    c.diagnostics.sort_by_key(|d| d.span.start);
+    c.diagnostics
+}
+
+struct Checker {
+    /// The types of the variables that are currently visible.
+    env:         HashMap<String, Type>,
+    /// The variables declared in every enclosing `{}` body, innermost last,
+    /// together with the types of the variables they shadow.
+    scopes:      Vec<Vec<(String, Option<Type>)>>,
+    /// The literal every constant is replaced with.
+    consts:      HashMap<String, Token>,
+    /// The number of parameters of every function, and where its name is.
+    funcs:       HashMap<String, (usize, Span)>,
+    /// The function being checked, or `None` for the top level.
+    func:        Option<String>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker {
+    fn check_statements(&mut self, statements: &[Node], top_level: bool) {
+        use Token::*;
+        for (i, n) in statements.iter().enumerate() {
+            match &n.token {
+                Branch => {
+                    self.check_cond(&n.children[0], "if");
+                    self.check_block(&n.children[1]);
+                    self.check_block(&n.children[2]);
+                },
+                While => {
+                    self.check_cond(&n.children[0], "while");
+                    self.check_block(&n.children[1]);
+                },
+                For => {
+                    // a variable declared in the init statement only lives inside the loop
+                    self.scopes.push(Vec::new());
+                    self.check_statements(std::slice::from_ref(&n.children[0]), false);
+                    self.check_cond(&n.children[1], "for");
+                    self.check_block(&n.children[3]);
+                    self.check_statements(std::slice::from_ref(&n.children[2]), false);
+                    self.exit_scope();
+                },
+                VarSig | Assign => self.check_assign(n),
+                Print | PrintNum => {
+                    let expr = &n.children[0];
+                    match self.value(expr) {
+                        Some(Type::Array(_)) => self.error(expr.span, "arrays can't be printed, only their elements"),
+                        Some(ty @ Type::Str(_)) if n.token == PrintNum => {
+                            self.error(expr.span, format!("`printnum` expects a number, not {}", ty));
+                        },
+                        _ => (),
+                    }
+                },
+                Return => {
+                    if self.func.is_none() {
+                        self.error(n.span, "`return` outside of a function");
+                    }
+                    self.check_u8(&n.children[0], "functions can only return `u8` numbers");
+                    if let Some(next) = statements.get(i + 1) {
+                        self.diagnostics.push(
+                            Diagnostic::warning(next.span, "unreachable statement")
+                                .with_note(format!("the `return` at {} always leaves the function before it", n.span)),
+                        );
+                    }
+                },
+                Call(_) => self.check_call(n),
+                ConstSig if top_level => (),
+                ConstSig => self.error(n.span, "constants can only be declared at the top level"),
+                FuncSig if top_level => self.check_function(n),
+                FuncSig => self.error(n.span, "functions can only be defined at the top level"),
+                _ => (),
+            }
+        }
+    }
+
+    /// Checks the statements of a `{}` body, which are in a scope of their own.
+    fn check_block(&mut self, body: &Node) {
+        self.scopes.push(Vec::new());
+        self.check_statements(&body.children, false);
+        self.exit_scope();
+    }
+
+    /// Ends the innermost scope, so the variables declared in it are no longer
+    /// visible and the ones they shadowed are again.
+    fn exit_scope(&mut self) {
+        for (name, shadowed) in self.scopes.pop().unwrap_or_default().into_iter().rev() {
+            self.env.remove(&name);
+            if let Some(ty) = shadowed {
+                self.env.insert(name, ty);
+            }
+        }
+    }
+
+    /// Adds the variable `name` of type `ty` to the innermost scope.
+    fn declare(&mut self, name: &str, ty: Type) {
+        let shadowed = self.env.insert(name.to_string(), ty);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push((name.to_string(), shadowed));
+        }
+    }
+
+    /// Checks if `name` was declared in the innermost scope.
+    fn in_scope(&self, name: &str) -> bool {
+        self.scopes
+            .last()
+            .is_some_and(|scope| scope.iter().any(|(declared, _)| declared == name))
+    }
+
+    /// Checks the declaration of the constant `name` at `span`, whose value is `expr`, and adds it
+    /// to `consts`. It can only use the constants declared before it.
+    fn check_const(&mut self, name: &str, span: Span, expr: &Node) {
+        if self.consts.contains_key(name) {
+            return self.error(span, format!("`{}` is already declared as a constant", name));
+        }
+        match const_literal(expr, &self.consts) {
+            Some(literal) => {
+                self.consts.insert(name.to_string(), literal);
+            },
+            None => match Type::of(&inline_consts(expr, &self.consts), &|_| None) {
+                Ok(ty) if !ty.is_numeric() => {
+                    self.error(expr.span, format!("constants can only be numbers or bools, not {}", ty));
+                },
+                Ok(_) => self.diagnostics.push(
+                    Diagnostic::error(expr.span, format!("the value of `{}` must be known at compile time", name))
+                        .with_note("constants can only use literals and the constants declared before them"),
+                ),
+                Err(error) => self.diagnostics.push(error),
+            },
+        }
+    }
+
+    /// Checks the body of a function, which only sees its parameters.
+    fn check_function(&mut self, node: &Node) {
+        let name = match &node.children[0].token {
+            Token::Ident(name) => name.clone(),
+            _ => return,
+        };
+        let params = &node.children[1].children;
+        for (i, param) in params.iter().enumerate() {
+            if let Token::Ident(param_name) = &param.token {
+                if self.consts.contains_key(param_name) {
+                    self.error(param.span, format!("`{}` is already declared as a constant", param_name));
+                } else if params[..i].iter().any(|p| p.token == param.token) {
+                    self.error(param.span, format!("`{}` is already declared as a parameter of `{}`", param_name, name));
+                }
+            }
+        }
+        let params = node.children[1].children
+            .iter()
+            .filter_map(|param| match &param.token {
+                Token::Ident(param) => Some((param.clone(), Type::U8)),
+                _ => None,
+            })
+            .collect();
+        let outer = std::mem::replace(&mut self.env, params);
+        self.func = Some(name);
+        self.check_block(&node.children[2]);
+        self.func = None;
+        self.env = outer;
+    }
+
+    fn check_cond(&mut self, cond: &Node, keyword: &str) {
+        self.check_number(cond, &format!("the condition of `{}` must be a number or a bool", keyword));
+    }
+
+    fn check_assign(&mut self, node: &Node) {
+        let target = &node.children[0];
+        let name_node = target.children.first().unwrap_or(target);
+        let name = match &name_node.token {
+            Token::Ident(name) => name,
+            _ => return,
+        };
+        if self.consts.contains_key(name) {
+            return if node.token == Token::VarSig {
+                self.error(name_node.span, format!("`{}` is already declared as a constant", name))
+            } else {
+                self.error(name_node.span, format!("`{}` is a constant and can't be assigned", name))
+            };
+        }
+
+        // `var a[N];` declares an array
+        if target.token == Token::Index && node.children.len() == 1 {
+            let len = &target.children[1];
+            let ty = match const_value(&inline_consts(len, &self.consts)) {
+                Some(0) => return self.error(len.span, "an array must have at least one element"),
+                Some(len) => Type::Array(len as usize),
+                None => return self.error(len.span, "the length of an array must be a constant"),
+            };
+            if self.in_scope(name) {
+                self.error(name_node.span, format!("`{}` is already declared in this scope", name));
+            } else {
+                self.declare(name, ty);
+            }
+            return;
+        }
+
+        if target.token == Token::Index {
+            let target = &inline_consts(target, &self.consts);
+            self.check_names(target);
+            if let Err(error) = Type::check_index(target, &|name| self.env.get(name).copied()) {
+                self.diagnostics.push(error);
+            }
+            self.check_number(&node.children[1], "array elements can only hold numbers");
+            return;
+        }
+
+        let declaration = node.token == Token::VarSig;
+        let declared = self.env.get(name).copied();
+        let duplicate = declaration && self.in_scope(name);
+        if duplicate {
+            self.error(name_node.span, format!("`{}` is already declared in this scope", name));
+        } else if !declaration && declared.is_none() {
+            self.diagnostics.push(
+                Diagnostic::error(name_node.span, format!("`{}` is not declared", name))
+                    .with_note(format!("use `var {} = ...` to declare it", name)),
+            );
+        }
+
+        let expr = &node.children[1];
+        let value = match self.value(expr) {
+            Some(Type::Array(_)) => return self.error(expr.span, "arrays can't be assigned, only their elements"),
+            Some(value) => value,
+            None => return,
+        };
+        let ty = match node.children.get(2) {
+            Some(Node { token: Token::TypeName(type_name), span, .. }) => match Type::named(type_name) {
+                Some(declared) if !declared.can_hold(value) => {
+                    return self.error(expr.span, format!(
+                        "{} can't be assigned to a variable of type {}",
+                        value, declared
+                    ));
+                },
+                Some(declared) => declared,
+                None => return self.error(*span, format!("unknown type `{}`", type_name)),
+            },
+            _ => value,
+        };
+        match declared {
+            Some(var) if !declaration => {
+                if !var.can_hold(ty) {
+                    self.error(expr.span, format!("`{}` is {} and can't be assigned {}", name, var, ty));
+                }
+            },
+            Some(_) if duplicate => (),
+            // an undeclared variable is declared anyway, so its uses aren't reported as well
+            _ => self.declare(name, ty),
+        }
+    }
+
+    /// Checks the call `node` to a user defined function and its arguments.
+    fn check_call(&mut self, node: &Node) {
+        let name = match &node.token {
+            Token::Call(name) => name,
+            _ => return,
+        };
+        match self.funcs.get(name) {
+            None => self.error(node.span, format!("call to undefined function `{}`", name)),
+            Some(&(params, span)) if params != node.children.len() => self.diagnostics.push(
+                Diagnostic::error(node.span, format!(
+                    "function `{}` takes {} argument(s) but {} were given",
+                    name, params, node.children.len()
+                ))
+                .with_note(format!("`{}` is defined at {}", name, span)),
+            ),
+            Some(_) => (),
+        }
+        for arg in &node.children {
+            self.check_u8(arg, "arguments can only be `u8` numbers");
+        }
+    }
+
+    /// Checks that `expr` fits in the single cell parameters and return values are
+    /// passed in, reporting `msg` if it doesn't.
+    fn check_u8(&mut self, expr: &Node, msg: &str) {
+        match self.value(expr) {
+            Some(Type::U8 | Type::Bool) | None => (),
+            Some(ty) => self.diagnostics.push(
+                Diagnostic::error(expr.span, format!("{}, not {}", msg, ty))
+                    .with_note("parameters and return values are `u8`, so other values would be truncated"),
+            ),
+        }
+    }
+
+    /// Checks that `expr` is a number or a bool, reporting `msg` if it isn't.
+    fn check_number(&mut self, expr: &Node, msg: &str) {
+        match self.value(expr) {
+            Some(ty) if !ty.is_numeric() => self.error(expr.span, format!("{}, not {}", msg, ty)),
+            _ => (),
+        }
+    }
+
+    /// The type of the expression `expr`, if it has one. Also checks the calls and
+    /// variables in it.
+    fn value(&mut self, expr: &Node) -> Option<Type> {
+        let expr = &inline_consts(expr, &self.consts);
+        self.check_names(expr);
+        match Type::of(expr, &|name| self.env.get(name).copied()) {
+            Ok(ty) => Some(ty),
+            Err(error) => {
+                self.diagnostics.push(error);
+                None
+            },
+        }
+    }
+
+    /// Checks that every call below `node` is to a defined function and that every
+    /// variable is declared, including those nested in other expressions.
+    fn check_names(&mut self, node: &Node) {
+        for n in &node.children {
+            match &n.token {
+                Token::Call(_) => self.check_call(n),
+                Token::Ident(name) if !self.env.contains_key(name) => {
+                    self.error(n.span, format!("`{}` is not declared", name));
+                },
+                _ => self.check_names(n),
+            }
+        }
+    }
+
+    fn error(&mut self, span: Span, msg: impl Into<String>) {
+        self.diagnostics.push(Diagnostic::error(span, msg));
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Type {
+    /// A single cell.
+    U8,
+    /// Two cells, least significant first.
+    U16,
+    /// Four cells, least significant first.
+    U32,
+    /// A single cell holding a number in two's complement, so 255 is -1.
+    I8,
+    /// Two cells holding a number in two's complement, least significant first.
+    I16,
+    /// A single cell that is either 0 or 1. Can be used as a number wherever one is expected.
+    Bool,
+    /// Up to the given number of characters, stored in contiguous cells followed
+    /// by a zero. Shorter strings are terminated by a zero as well, and every
+    /// cell after the terminating zero is zero too.
+    Str(usize),
+    /// The given number of numbers. Every element has two scratch cells in front of it,
+    /// and the array starts with two more, so it looks like `[c d _ c d e0 c d e1 ...]`.
+    /// The scratch cells are zero outside of `Parser::load` and `Parser::store`, which
+    /// move a marker along them to reach elements at indices only known at runtime.
+    Array(usize),
+}
+
+impl Type {
+    /// The type called `name` in a declaration like `var x: u16 = 0;`, if there is one.
+    pub(crate) fn named(name: &str) -> Option<Self> {
+        match name {
+            "u8" => Some(Type::U8),
+            "u16" => Some(Type::U16),
+            "u32" => Some(Type::U32),
+            "i8" => Some(Type::I8),
+            "i16" => Some(Type::I16),
+            "bool" => Some(Type::Bool),
+            _ => None,
+        }
+    }
+
+    /// The smallest number type that can hold `val`.
+    pub(crate) fn of_literal(val: u32) -> Self {
+        if val <= u8::MAX as u32 {
+            Type::U8
+        } else if val <= u16::MAX as u32 {
+            Type::U16
+        } else {
+            Type::U32
+        }
+    }
+
+    /// The number of cells a value of this type occupies.
+    pub(crate) fn size(self) -> usize {
+        match self {
+            Type::U8 | Type::I8 | Type::Bool => 1,
+            Type::U16 | Type::I16 => 2,
+            Type::U32 => 4,
+            Type::Str(len) => len + 1,
+            Type::Array(len) => 3 * (len + 1),
+        }
+    }
+
+    pub(crate) fn is_int(self) -> bool {
+        matches!(self, Type::U8 | Type::U16 | Type::U32 | Type::I8 | Type::I16)
+    }
+
+    pub(crate) fn is_signed(self) -> bool {
+        matches!(self, Type::I8 | Type::I16)
+    }
+
+    /// Checks if values of this type can be used as numbers, which bools can as 0 or 1.
+    pub(crate) fn is_numeric(self) -> bool {
+        self.is_int() || self == Type::Bool
+    }
+
+    /// The number type a value of this type is used as in arithmetic.
+    pub(crate) fn as_number(self) -> Self {
+        if self == Type::Bool { Type::U8 } else { self }
+    }
+
+    /// Checks if a variable of this type can be assigned a value of type `value`.
+    /// Numbers can be assigned to any number type, and are truncated if they don't fit,
+    /// but a bool can only be assigned another bool.
+    pub(crate) fn can_hold(self, value: Type) -> bool {
+        match (self, value) {
+            (Type::Str(len), Type::Str(value_len)) => value_len <= len,
+            (Type::Bool, value) => value == Type::Bool,
+            _ => self.is_int() && value.is_numeric(),
+        }
+    }
+
+    /// The type of the value `expr` evaluates to, where `lookup` gives the types of variables.
+    /// Returns an error at the offending node if an operator is applied to operands it doesn't
+    /// support, or if an array is indexed out of bounds.
+    pub(crate) fn of(expr: &Node, lookup: &dyn Fn(&str) -> Option<Type>) -> Result<Self, Diagnostic> {
+        use Token::{BinOp, BoolLit, GetLine, Ident, Index, Len, Neg, Not, NumLit, StrLit};
+        Type::check_signedness(expr, lookup)?;
+        let mut stack = Vec::new();
+        for n in &expr.children {
+            let ty = match &n.token {
+                NumLit(val) => Type::of_literal(*val),
+                BoolLit(_) => Type::Bool,
+                StrLit(s) => Type::Str(unescape(s).len()),
+                GetLine => Type::Str(LINE_SIZE),
+                Ident(name) => lookup(name).unwrap_or(Type::U8),
+                Index => {
+                    Type::check_index(n, lookup)?;
+                    Type::U8
+                },
+                Len => {
+                    if n.children.len() != 1 {
+                        return Err(Diagnostic::error(
+                            n.span,
+                            format!("`len` takes 1 argument but {} were given", n.children.len()),
+                        ));
+                    }
+                    match Type::of(&n.children[0], lookup)? {
+                        Type::Str(_) => (),
+                        ty => return Err(Diagnostic::error(
+                            n.children[0].span,
+                            format!("`len` expects a string, not {}", ty),
+                        )),
+                    }
+                    Type::U8
+                },
+                Not | Neg | BinOp(BiOp::And) | BinOp(BiOp::Or) => {
+                    let operand = stack.pop().unwrap_or(Type::U8);
+                    for ty in n.children.iter().map(|rhs| Type::of(rhs, lookup)).chain([Ok(operand)]) {
+                        let ty = ty?;
+                        if !ty.is_numeric() {
+                            return Err(Diagnostic::error(n.span, format!(
+                                "logical operators and negation can only be applied to numbers and bools, not {}",
+                                ty
+                            )));
+                        }
+                    }
+                    if n.token == Neg { operand.as_number() } else { Type::Bool }
+                },
+                BinOp(op) => {
+                    let rhs = stack.pop().unwrap_or(Type::U8);
+                    let lhs = stack.pop().unwrap_or(Type::U8);
+                    match (lhs, rhs) {
+                        _ if lhs.is_numeric() && rhs.is_numeric() && is_comparison(*op) => Type::Bool,
+                        _ if lhs.is_numeric() && rhs.is_numeric() => {
+                            let (lhs, rhs) = (lhs.as_number(), rhs.as_number());
+                            if lhs.size() > rhs.size() || (lhs.size() == rhs.size() && lhs.is_signed()) {
+                                lhs
+                            } else {
+                                rhs
+                            }
+                        },
+                        (Type::Array(_), _) | (_, Type::Array(_)) => return Err(Diagnostic::error(
+                            n.span,
+                            "arrays can only be used through their elements, like `a[0]`",
+                        )),
+                        (Type::Str(a), Type::Str(b)) if *op == BiOp::Add => Type::Str(a + b),
+                        _ if *op == BiOp::Add => {
+                            return Err(Diagnostic::error(n.span, "a string can only be added to another string"))
+                        },
+                        _ => return Err(Diagnostic::error(n.span, format!("`{}` can't be applied to strings", op))),
+                    }
+                },
+                _ => Type::U8,
+            };
+            stack.push(ty);
+        }
+        Ok(stack.pop().unwrap_or(Type::U8))
+    }
+
+    /// Checks that the expression `expr` doesn't order or divide signed and unsigned values.
+    /// An expression is evaluated as signed if any of its variables is, so an unsigned value
+    /// like 200 in a `u8` would be read as -56 next to an `i8`.
+    fn check_signedness(expr: &Node, lookup: &dyn Fn(&str) -> Option<Type>) -> Result<(), Diagnostic> {
+        let op = expr.children.iter().find(|n| match n.token {
+            Token::BinOp(op) => is_ordering(op) || op == BiOp::Div || op == BiOp::Mod,
+            _ => false,
+        });
+        let signed = expr.children.iter().find_map(|n| match &n.token {
+            Token::Ident(name) => lookup(name).filter(|ty| ty.is_signed()).map(|ty| (name, ty)),
+            _ => None,
+        });
+        // literals are left out, since they are converted to the type of the expression
+        let unsigned = expr.children.iter().find_map(|n| match &n.token {
+            Token::Ident(name) => match lookup(name) {
+                Some(ty @ (Type::U8 | Type::U16 | Type::U32)) => Some(format!("`{}` is {}", name, ty)),
+                _ => None,
+            },
+            Token::Index => Some("array elements are `u8`".to_string()),
+            Token::Call(_) | Token::Len | Token::GetChar | Token::ReadNum => Some(format!("{} returns `u8`", n.token)),
+            _ => None,
+        });
+        match (op, signed, unsigned) {
+            (Some(op), Some((name, ty)), Some(unsigned)) => Err(Diagnostic::error(
+                op.span,
+                format!("{} can't be applied to both signed and unsigned values", op.token),
+            )
+            .with_note(format!("`{}` is {}, but {}", name, ty, unsigned))
+            .with_note("assign one of them to a variable of the other's type first")),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that the `Index` node `node` indexes an array with a number, which is
+    /// in bounds if it is a constant and a `u8` otherwise.
+    pub(crate) fn check_index(node: &Node, lookup: &dyn Fn(&str) -> Option<Type>) -> Result<(), Diagnostic> {
+        let index = &node.children[1];
+        if let Token::Ident(name) = &node.children[0].token {
+            match lookup(name) {
+                Some(Type::Array(len)) => match const_value(index) {
+                    Some(i) if i as usize >= len => return Err(Diagnostic::error(
+                        index.span,
+                        format!("index {} is out of bounds for `{}`, which has {} elements", i, name, len),
+                    )),
+                    _ => (),
+                },
+                None => (),
+                Some(ty) => return Err(Diagnostic::error(
+                    node.children[0].span,
+                    format!("`{}` is {} and can't be indexed", name, ty),
+                )),
+            }
+        }
+        let ty = Type::of(index, lookup)?;
+        if !ty.is_numeric() {
+            return Err(Diagnostic::error(index.span, format!("an array index must be a number, not {}", ty)));
+        }
+        if !matches!(ty, Type::U8 | Type::Bool) && const_value(index).is_none() {
+            return Err(Diagnostic::error(index.span, format!("an array index must be a `u8`, not {}", ty))
+                .with_note("indices only known at runtime are a single cell, only constant ones can be larger"));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::U8 => write!(f, "`u8`"),
+            Type::U16 => write!(f, "`u16`"),
+            Type::U32 => write!(f, "`u32`"),
+            Type::I8 => write!(f, "`i8`"),
+            Type::I16 => write!(f, "`i16`"),
+            Type::Bool => write!(f, "`bool`"),
+            Type::Str(len) => write!(f, "a string of up to {} characters", len),
+            Type::Array(len) => write!(f, "an array of {} elements", len),
+        }
+    }
+}
+
+/// Evaluates every operation in an expression whose operands are all literals,
+/// so it costs nothing at runtime. Values wrap around the same way numbers of
+/// `w` cells do, and are compared and divided as signed numbers if `signed` is set.
+pub(crate) fn fold_constants(expr: &Node, w: usize, signed: bool) -> Node {
+    // every entry is the part of the expression that computes one value
+    let mut stack: Vec<Vec<Node>> = Vec::new();
+    for n in &expr.children {
+        if let (&Token::BinOp(op @ BiOp::And), [rhs]) | (&Token::BinOp(op @ BiOp::Or), [rhs]) =
+            (&n.token, n.children.as_slice())
+        {
+            let rhs = fold_constants(rhs, w, signed);
+            let lhs = match stack.last_mut() {
+                Some(lhs) => lhs,
+                None => return expr.clone(),
+            };
+            match (lhs.as_slice(), rhs.children.as_slice()) {
+                ([Node { token: Token::NumLit(x), .. }], [Node { token: Token::NumLit(y), .. }]) => {
+                    *lhs = vec![Node::leaf(Token::NumLit(eval(op, *x, *y, w, signed)))];
+                },
+                _ => lhs.push(Node::new(n.token.clone(), vec![rhs])),
+            }
+        } else if n.token == Token::Not || n.token == Token::Neg {
+            match stack.last_mut() {
+                Some(operand) => match operand.as_slice() {
+                    [Node { token: Token::NumLit(x), .. }] => {
+                        let val = if n.token == Token::Not { (*x == 0) as u32 } else { x.wrapping_neg() & mask(w) };
+                        *operand = vec![Node::leaf(Token::NumLit(val))];
+                    },
+                    _ => operand.push(n.clone()),
+                },
+                None => return expr.clone(),
+            }
+        } else if let Token::BinOp(op) = n.token {
+            if stack.len() < 2 {
+                // leave malformed expressions for codegen to report
+                return expr.clone();
+            }
+            let b = stack.pop().unwrap();
+            let mut a = stack.pop().unwrap();
+            match (a.as_slice(), b.as_slice()) {
+                ([Node { token: Token::NumLit(x), .. }], [Node { token: Token::NumLit(y), .. }]) => {
+                    a = vec![Node::leaf(Token::NumLit(eval(op, *x, *y, w, signed)))];
+                },
+                _ => {
+                    a.extend(b);
+                    a.push(n.clone());
+                },
+            }
+            stack.push(a);
+        } else if let Token::BoolLit(val) = n.token {
+            stack.push(vec![Node::leaf(Token::NumLit(val as u32))]);
+        } else {
+            stack.push(vec![n.clone()]);
+        }
+    }
+    Node::new(Token::Expr, stack.into_iter().flatten().collect())
+}
+
+/// The literal the expression `expr` evaluates to if it only depends on literals and the
+/// constants in `consts`, which maps every constant to its literal. Constants hold the
+/// exact value of their expression, since it is evaluated with four cells.
+pub(crate) fn const_literal(expr: &Node, consts: &HashMap<String, Token>) -> Option<Token> {
+    let expr = inline_consts(expr, consts);
+    let ty = Type::of(&expr, &|_| None).ok()?;
+    let val = const_value(&expr)?;
+    Some(if ty == Type::Bool { Token::BoolLit(val != 0) } else { Token::NumLit(val) })
+}
+
+/// Replaces the constants in the expressions below `node` with their literals from
+/// `consts`, and removes their declarations.
+pub(crate) fn inline_consts(node: &Node, consts: &HashMap<String, Token>) -> Node {
+    let children = node.children
+        .iter()
+        .filter(|n| n.token != Token::ConstSig)
+        .map(|n| match &n.token {
+            Token::Ident(name) if node.token == Token::Expr && consts.contains_key(name) => {
+                Node::leaf(consts[name].clone()).at(n.span)
+            },
+            _ => inline_consts(n, consts),
+        })
+        .collect();
+    Node::new(node.token.clone(), children).at(node.span)
+}
+
+/// The value of `expr` if it only depends on literals.
+pub(crate) fn const_value(expr: &Node) -> Option<u32> {
+    match fold_constants(expr, 4, false).children.as_slice() {
+        [Node { token: Token::NumLit(val), .. }] => Some(*val),
+        _ => None,
+    }
+}
+
+/// Computes `lhs op rhs` the same way the generated code would for numbers of `w` cells.
+fn eval(op: BiOp, lhs: u32, rhs: u32, w: usize, signed: bool) -> u32 {
+    use BiOp::*;
+    if signed {
+        // sign extends the operands, so the operations below work on their actual values
+        let shift = 64 - 8 * w as u32;
+        let x = ((lhs as i64) << shift) >> shift;
+        let y = ((rhs as i64) << shift) >> shift;
+        let val = match op {
+            Div             => x.checked_div(y).unwrap_or(0),
+            Mod             => x.checked_rem(y).unwrap_or(x),
+            LessOrEqual     => (x <= y) as i64,
+            GreaterOrEqual  => (x >= y) as i64,
+            Less            => (x < y) as i64,
+            Greater         => (x > y) as i64,
+            _ => return eval(op, lhs, rhs, w, false),
+        };
+        return val as u32 & mask(w);
+    }
+    let val = match op {
+        Add             => lhs.wrapping_add(rhs),
+        Sub             => lhs.wrapping_sub(rhs),
+        Mul             => lhs.wrapping_mul(rhs),
+        Div             => lhs.checked_div(rhs).unwrap_or(0),
+        Mod             => lhs.checked_rem(rhs).unwrap_or(lhs),
+        Pow             => lhs.wrapping_pow(rhs),
+        Equal           => (lhs == rhs) as u32,
+        NotEqual        => (lhs != rhs) as u32,
+        LessOrEqual     => (lhs <= rhs) as u32,
+        GreaterOrEqual  => (lhs >= rhs) as u32,
+        Less            => (lhs < rhs) as u32,
+        Greater         => (lhs > rhs) as u32,
+        And             => (lhs != 0 && rhs != 0) as u32,
+        Or              => (lhs != 0 || rhs != 0) as u32,
+        Invalid         => panic!("invalid operator"),
+    };
+    val & mask(w)
+}
+
+/// Checks if `op` compares its operands, so its result is a bool.
+fn is_comparison(op: BiOp) -> bool {
+    matches!(op, BiOp::Equal | BiOp::NotEqual) || is_ordering(op)
+}
+
+/// Checks if `op` compares the order of its operands.
+pub(crate) fn is_ordering(op: BiOp) -> bool {
+    use BiOp::*;
+    matches!(op, LessOrEqual | GreaterOrEqual | Less | Greater)
+}
+
+/// The largest number that fits in `w` cells.
+pub(crate) fn mask(w: usize) -> u32 {
+    u32::MAX.checked_shr(32 - 8 * w as u32).unwrap_or(0)
+}
@@ -0,0 +1,105 @@
+use std::fmt;
+
+use crate::token::Span;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    /// The program can't be compiled.
+    Error,
+    /// The program can be compiled, but likely doesn't do what was intended.
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A problem found while compiling a program.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message:  String,
+    /// The part of the source code the problem is in. The default span if it isn't
+    /// about any part in particular.
+    pub span:     Span,
+    /// Further explanations, printed below the source excerpt.
+    pub notes:    Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(span: Span, message: impl Into<String>) -> Self {
+        Diagnostic { severity: Severity::Error, message: message.into(), span, notes: Vec::new() }
+    }
+
+    pub fn warning(span: Span, message: impl Into<String>) -> Self {
+        Diagnostic { severity: Severity::Warning, ..Diagnostic::error(span, message) }
+    }
+
+    /// Adds the note `note` to the diagnostic.
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Renders the diagnostic with the line of `source` it is about and a caret under
+    /// its span, like
+    ///
+    /// ```text
+    /// error: `b` is not declared
+    ///  --> main.b:5:3
+    ///   |
+    /// 5 |   b = 3;
+    ///   |   ^
+    /// ```
+    ///
+    /// where `path` is the file `source` was read from.
+    pub fn render(&self, source: &str, path: &str) -> String {
+        let mut out = format!("{}: {}\n", self.severity, self.message);
+        let gutter = " ".repeat(self.span.line.to_string().len());
+        let line = match source.lines().nth(self.span.line.wrapping_sub(1)) {
+            Some(line) => line,
+            // the location is still printed if there is no line to show, unless the
+            // diagnostic isn't about any part of the program
+            None => {
+                if self.span != Span::default() {
+                    out += &format!("{}--> {}:{}\n", gutter, path, self.span);
+                }
+                for note in &self.notes {
+                    out += &format!("{} = note: {}\n", gutter, note);
+                }
+                return out;
+            },
+        };
+
+        // tabs are kept in front of the caret, so it lines up with the excerpt
+        let indent: String = line
+            .chars()
+            .take(self.span.col - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        // a span covering several lines is only underlined up to the end of the first
+        let rest: String = line.chars().skip(self.span.col - 1).collect();
+        let len = source
+            .get(self.span.start..self.span.end)
+            .map_or(1, |text| text.chars().take_while(|&c| c != '\n').count())
+            .clamp(1, rest.chars().count().max(1));
+
+        out += &format!("{}--> {}:{}\n", gutter, path, self.span);
+        out += &format!("{} |\n", gutter);
+        out += &format!("{} | {}\n", self.span.line, line);
+        out += &format!("{} | {}{}\n", gutter, indent, "^".repeat(len));
+        for note in &self.notes {
+            out += &format!("{} = note: {}\n", gutter, note);
+        }
+        out
+    }
+}
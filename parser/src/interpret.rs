@@ -57,7 +57,7 @@ impl Interpreter {
             '-' => self.memory[self.memory_ptr] = self.memory[self.memory_ptr].wrapping_sub(1),
             ',' => {
                 let mut c = getchar();
-                while let None = c {
+                while c.is_none() {
                     c = getchar();
                 }
                 self.memory[self.memory_ptr] = c.unwrap() as u8;
@@ -113,7 +113,7 @@ impl fmt::Display for Interpreter {
 
         for (i, m) in self.memory.iter().enumerate() {
             if i % (WIDTH / (DELTA * 2)) == 0 {
-                writeln!(f, "")?;
+                writeln!(f)?;
             }
             if i == self.memory_ptr {
                 write!(f, "[{:^3}", m)?;
@@ -135,7 +135,7 @@ impl fmt::Display for Interpreter {
                 if self.instruction_ptr >= i && self.instruction_ptr < i + WIDTH {
                     writeln!(f, "\n{:>1$}", "v", self.instruction_ptr % WIDTH + 1)?;
                 } else {
-                    writeln!(f, "")?;
+                    writeln!(f)?;
                 }
             }
             write!(f, "{}", c)?;
@@ -1,21 +1,169 @@
-use std::io::Read;
+use std::path::Path;
+use std::process;
 
+use diagnostic::Diagnostic;
+use parse::EofMode;
 use token::tokenize;
 
+mod check;
+mod diagnostic;
 mod parse;
 mod token;
 mod interpret;
 
-fn main() {
-    let mut program = String::with_capacity(8192);
-    std::io::stdin().read_to_string(&mut program).unwrap();
-    let tokens = tokenize(program);
-    println!("tokens:\n{:#?}", tokens);
+const USAGE: &str = "\
+usage: parser <command> [options]
+
+commands:
+    compile <file.b> [-o <file.bf>]         compiles a brang program to Brainfuck
+    run <file.bf>                           runs a Brainfuck program in the visualizer
+    build-and-run <file.b> [-o <file.bf>]   compiles a brang program and runs the result
+
+options:
+    -o, --output <file.bf>  where to write the compiled program (defaults to the input path with a `.bf` extension)
+    --eof <mode>            what the Brainfuck implementation does with a cell when reading past the end
+                            of input: `zero` (default), `minus-one` or `unchanged`
+    --tokens                prints the token stream after tokenizing
+    --tree                  prints the syntax tree after parsing
+    -h, --help              prints this message";
+
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    Compile,
+    Run,
+    BuildAndRun,
+}
+
+#[derive(Debug)]
+struct Args {
+    command:     Command,
+    input:       String,
+    output:      Option<String>,
+    eof:         EofMode,
+    show_tokens: bool,
+    show_tree:   bool,
+}
+
+impl Args {
+    /// Parses the command line arguments (excluding the binary name).
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let command = match args.next().as_deref() {
+            Some("compile")         => Command::Compile,
+            Some("run")             => Command::Run,
+            Some("build-and-run")   => Command::BuildAndRun,
+            Some("-h") | Some("--help") => return Err(String::new()),
+            Some(other)             => return Err(format!("unknown command `{}`", other)),
+            None                    => return Err("missing command".to_string()),
+        };
+
+        let mut input = None;
+        let mut output = None;
+        let mut eof = None;
+        let mut show_tokens = false;
+        let mut show_tree = false;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-o" | "--output" => match args.next() {
+                    Some(path) => output = Some(path),
+                    None => return Err(format!("`{}` expects a file path", arg)),
+                },
+                "--eof" => eof = match args.next().as_deref() {
+                    Some("zero")        => Some(EofMode::Zero),
+                    Some("minus-one")   => Some(EofMode::MinusOne),
+                    Some("unchanged")   => Some(EofMode::Unchanged),
+                    _ => return Err("`--eof` expects `zero`, `minus-one` or `unchanged`".to_string()),
+                },
+                "--tokens"  => show_tokens = true,
+                "--tree"    => show_tree = true,
+                "-h" | "--help" => return Err(String::new()),
+                _ if arg.starts_with('-') => return Err(format!("unknown option `{}`", arg)),
+                _ if input.is_none() => input = Some(arg),
+                _ => return Err(format!("unexpected argument `{}`", arg)),
+            }
+        }
+
+        let input = input.ok_or_else(|| "missing input file".to_string())?;
+        if command == Command::Run && (output.is_some() || eof.is_some() || show_tokens || show_tree) {
+            return Err("`run` only takes a Brainfuck file".to_string());
+        }
 
-    let root = token::parse(tokens);
-    println!("tree:\n{}", root);
+        let eof = eof.unwrap_or(EofMode::Zero);
+        Ok(Args { command, input, output, eof, show_tokens, show_tree })
+    }
+
+    /// The path the compiled program is written to.
+    fn output_path(&self) -> String {
+        match &self.output {
+            Some(path) => path.clone(),
+            None => Path::new(&self.input)
+                .with_extension("bf")
+                .to_string_lossy()
+                .into_owned(),
+        }
+    }
+}
+
+fn compile(args: &Args) -> Result<String, String> {
+    let program = std::fs::read_to_string(&args.input)
+        .map_err(|e| format!("could not read `{}`: {}", args.input, e))?;
+    // prints the diagnostics, and returns the message to fail with if there are errors
+    let report = |diagnostics: &[Diagnostic]| {
+        for diagnostic in diagnostics {
+            eprintln!("{}", diagnostic.render(&program, &args.input));
+        }
+        match diagnostics.iter().filter(|d| d.is_error()).count() {
+            1 => format!("could not compile `{}` due to the previous error", args.input),
+            errors => format!("could not compile `{}` due to {} previous errors", args.input, errors),
+        }
+    };
+
+    let tokens = tokenize(&program).map_err(|errors| report(&errors))?;
+    if args.show_tokens {
+        println!("tokens:\n{:#?}", tokens);
+    }
+
+    let root = token::parse(tokens).map_err(|errors| report(&errors))?;
+    if args.show_tree {
+        println!("tree:\n{}", root);
+    }
+
+    let diagnostics = check::check(&root);
+    let failed = diagnostics.iter().any(Diagnostic::is_error);
+    let summary = report(&diagnostics);
+    if failed {
+        return Err(summary);
+    }
+
+    let code = parse::to_brainfuck(root, args.eof).map_err(|errors| report(&errors))?;
+    let output = args.output_path();
+    std::fs::write(&output, code).map_err(|e| format!("could not write `{}`: {}", output, e))?;
+    Ok(output)
+}
+
+fn main() {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(msg) if msg.is_empty() => {
+            println!("{}", USAGE);
+            return;
+        },
+        Err(msg) => {
+            eprintln!("error: {}\n\n{}", msg, USAGE);
+            process::exit(2);
+        },
+    };
 
-    parse::to_brainfuck(root, "tests/b.bf").unwrap();
+    let result = match args.command {
+        Command::Compile => compile(&args).map(|_| ()),
+        Command::Run => {
+            interpret::run(&args.input);
+            Ok(())
+        },
+        Command::BuildAndRun => compile(&args).map(|output| interpret::run(&output)),
+    };
 
-    interpret::run("tests/b.bf");
-}
\ No newline at end of file
+    if let Err(msg) = result {
+        eprintln!("error: {}", msg);
+        process::exit(1);
+    }
+}
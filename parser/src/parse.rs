@@ -1,48 +1,176 @@
 use std::collections::HashMap;
-use std::io::Write;
-use std::fs::OpenOptions;
 
 pub(crate) use u8 as num;
 
+use crate::check::const_literal;
+use crate::check::const_value;
+use crate::check::fold_constants;
+use crate::check::inline_consts;
+use crate::check::is_ordering;
+use crate::check::mask;
+use crate::check::Type;
+use crate::check::LINE_SIZE;
+use crate::diagnostic::Diagnostic;
+use crate::token::unescape;
+use crate::token::BiOp;
 use crate::token::Node;
 use crate::token::Token;
 
-pub fn to_brainfuck(root: Node, output: &str) -> Result<(), std::io::Error> {
-    let mut c = Parser::new();
-    
-    c.process_node(&root);
-
-    let mut file = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(output)?;
-    
-    write!(file, "{}", c.out)?;
-    Ok(())
+/// What the Brainfuck implementation running the program does when `,` reaches the end of input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EofMode {
+    /// The cell is set to 0.
+    Zero,
+    /// The cell is set to -1 (255).
+    MinusOne,
+    /// The cell is left unchanged.
+    Unchanged,
+}
+
+impl EofMode {
+    /// The value `,` leaves in a zeroed cell at end of input.
+    fn value(self) -> num {
+        match self {
+            EofMode::Zero | EofMode::Unchanged => 0,
+            EofMode::MinusOne => num::MAX,
+        }
+    }
+}
+
+/// Compiles the program `root` to Brainfuck. Returns the errors found while compiling if
+/// there are any, which are only those the type checker can't find before.
+pub fn to_brainfuck(root: Node, eof: EofMode) -> Result<String, Vec<Diagnostic>> {
+    let mut c = Parser::new(eof);
+
+    // constants are replaced by their values, so they don't need any cells
+    let mut consts = HashMap::new();
+    for n in &root.children {
+        if let (Token::ConstSig, [Node { token: Token::Ident(name), .. }, expr]) = (&n.token, n.children.as_slice()) {
+            match const_literal(expr, &consts) {
+                Some(literal) => consts.insert(name.clone(), literal),
+                None => {
+                    c.errors.push(Diagnostic::error(
+                        expr.span,
+                        format!("the value of constant `{}` must be known at compile time", name),
+                    ));
+                    None
+                },
+            };
+        }
+    }
+    let root = inline_consts(&root, &consts);
+
+    // functions are hoisted, so they can be called before their definition
+    for n in &root.children {
+        if let Node { token: Token::FuncSig, children, .. } = n {
+            if let Token::Ident(name) = &children[0].token {
+                c.funcs.insert(name.clone(), n.clone());
+            }
+        }
+    }
+    if c.funcs.keys().any(|name| c.is_recursive(name)) {
+        c.process_dispatch(&root);
+    } else {
+        c.process_node(&root);
+    }
+
+    if c.cells > Parser::ARRAY_SIZE {
+        c.errors.push(Diagnostic::error(root.span, format!(
+            "the program needs {} cells, but Brainfuck only guarantees {}",
+            c.cells, Parser::ARRAY_SIZE
+        )));
+    }
+    if c.errors.is_empty() {
+        Ok(c.out)
+    } else {
+        Err(c.errors)
+    }
 }
 
 struct Parser {
     ptr:    usize,
-    env:    HashMap<String, usize>,
+    env:    HashMap<String, Var>,
+    /// The variables declared in every enclosing `{}` body, innermost last,
+    /// together with the variables they shadow.
+    scopes: Vec<Vec<(String, Option<Var>)>>,
     allocd: HashMap<usize, usize>,
+    funcs:  HashMap<String, Node>,
+    frames: Vec<Frame>,
+    eof:    EofMode,
     out:    String,
+    /// The number of cells the program uses, which is one past the highest cell alloc'd.
+    cells:  usize,
+    errors: Vec<Diagnostic>,
+}
+
+/// A variable and where its value is stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Var {
+    adr: usize,
+    ty:  Type,
+}
+
+/// A function call that is currently being inlined.
+struct Frame {
+    name:   String,
+    /// Cell the return value is written to.
+    result: usize,
+    /// Cell that is 1 until the function returns, after which it is 0.
+    alive:  usize,
 }
 
 impl Parser {
 
     const ARRAY_SIZE: usize = 30_000;
+    /// Number of values the call stack in dispatch mode can hold.
+    const STACK_SIZE: usize = 1024;
+    /// Number of cells of the block id in dispatch mode, so programs can have up to 65535 blocks.
+    const PC_SIZE: usize = 2;
 
     // Compile functions
 
     fn process_node(&mut self, node: &Node) {
+        self.enter_scope();
+        self.process_statements(&node.children, node.token == Token::Root);
+        self.exit_scope();
+    }
+
+    fn process_statements(&mut self, statements: &[Node], top_level: bool) {
         use Token::*;
-        for n in &node.children {
+        for (i, n) in statements.iter().enumerate() {
             match n {
                 Node { token: Branch, .. } => self.process_branch(n),
+                Node { token: While,  .. } => self.process_while(n),
+                Node { token: For,    .. } => self.process_for(n),
+                Node { token: VarSig, .. } => self.process_assign(n),
                 Node { token: Assign, .. } => self.process_assign(n),
                 Node { token: Print,  .. } => self.process_print(n),
-                _ => unimplemented!("{:?}", n.token),
+                Node { token: PrintNum, .. } => self.process_print(n),
+                Node { token: Return, .. } => self.process_return(n),
+                Node { token: Call(_), .. } => {
+                    let result = self.malloc(1);
+                    self.process_call(n, result);
+                    self.dealloc(result);
+                },
+                Node { token: FuncSig, .. } if top_level => (),
+                Node { token: FuncSig, .. } => unreachable!("rejected by check::check"),
+                _ => unreachable!("rejected by check::check"),
+            }
+
+            // statements after a possible `return` only run if the function is still alive
+            let rest = &statements[i + 1..];
+            if !rest.is_empty() && contains_return(n) {
+                if let Some(alive) = self.frames.last().map(|f| f.alive) {
+                    let flag = self.malloc(1);
+                    self.cpy(alive, flag);
+                    self.mov(flag);
+                    self.out.push_str("[[-]");
+                    self.process_statements(rest, false);
+                    self.mov(flag);
+                    self.out.push(']');
+                    self.dealloc(flag);
+                    return;
+                }
             }
         }
     }
@@ -58,7 +186,7 @@ impl Parser {
         self.set(else_flag, 1);
 
         // write expression result into `if_flag`
-        self.process_expr_node(expr, if_flag);
+        self.process_cond(expr, if_flag);
 
         // if `if_flag` is non zero then set `if_flag` and `else_flag` to zero
         self.mov(if_flag);
@@ -77,52 +205,450 @@ impl Parser {
         self.dealloc(if_flag);
     }
 
+    fn process_while(&mut self, node: &Node) {
+        self.process_loop(&node.children[0], &node.children[1], None);
+    }
+
+    fn process_for(&mut self, node: &Node) {
+        let init = &node.children[0];
+        let cond = &node.children[1];
+        let step = &node.children[2];
+        let body = &node.children[3];
+
+        // a variable declared in the init statement only lives inside the loop
+        self.enter_scope();
+        self.process_assign(init);
+        self.process_loop(cond, body, Some(step));
+        self.exit_scope();
+    }
+
+    /// Runs `body` followed by `step` while `cond` evaluates to non zero.
+    fn process_loop(&mut self, cond: &Node, body: &Node, step: Option<&Node>) {
+        // `flag` holds the result of the condition and is re-evaluated at
+        // the end of every iteration, so the loop exits when it is zero
+        let flag = self.malloc(1);
+        // a `return` inside the body must also end the loop
+        let alive = self.frames.last()
+            .filter(|_| contains_return(body))
+            .map(|f| f.alive);
+        self.process_cond(cond, flag);
+        if let Some(alive) = alive {
+            self.mask(alive, flag);
+        }
+
+        self.mov(flag);
+        self.out.push('[');
+        self.process_node(body);
+        if let Some(step) = step {
+            self.process_assign(step);
+        }
+        self.process_cond(cond, flag);
+        if let Some(alive) = alive {
+            self.mask(alive, flag);
+        }
+        self.mov(flag);
+        self.out.push(']');
+
+        self.dealloc(flag);
+    }
+
     fn process_assign(&mut self, node: &Node) {
-        let adr = if let Token::Ident(name) = &node.children[0].token {
-            self.assign(name)
+        let target = &node.children[0];
+        if target.token == Token::Index {
+            match node.children.get(1) {
+                Some(expr) => {
+                    if !self.type_of(expr).is_numeric() {
+                        unreachable!("rejected by check::check");
+                    }
+                    match self.element(target) {
+                        (adr, _, Some(index)) => self.process_expr_node(expr, Self::element_adr(adr, index)),
+                        (adr, len, None) => {
+                            let index = self.malloc(1);
+                            let value = self.malloc(1);
+                            self.process_expr_node(&target.children[1], index);
+                            self.process_expr_node(expr, value);
+                            self.store(adr, len, index, value);
+                            self.dealloc(value);
+                            self.dealloc(index);
+                        },
+                    }
+                },
+                None => self.declare_array(target),
+            }
+            return;
+        }
+
+        let expr = &node.children[1];
+        let ty = self.type_of(expr);
+        if let Type::Array(_) = ty {
+            unreachable!("rejected by check::check");
+        }
+        let ty = match declared_type(node) {
+            Some(declared) if !declared.can_hold(ty) => unreachable!("rejected by check::check"),
+            Some(declared) => declared,
+            None => ty,
+        };
+        let var = if let Token::Ident(name) = &node.children[0].token {
+            self.assign(name, ty, node.token == Token::VarSig)
         } else { panic!("no identifier in assign block") };
-        self.process_expr_node(&node.children[1], adr);
+        match var.ty {
+            Type::U8 | Type::U16 | Type::U32 | Type::I8 | Type::I16 | Type::Bool => {
+                self.process_expr_into(expr, var.adr, var.ty);
+            },
+            Type::Str(cap) => {
+                let (adr, len) = self.process_str_expr(expr);
+                for i in 0..=len {
+                    self.movval(adr + i, var.adr + i);
+                }
+                for i in len + 1..=cap {
+                    self.set(var.adr + i, 0);
+                }
+                self.dealloc(adr);
+            },
+            Type::Array(_) => unreachable!(),
+        }
+    }
+
+    /// Adds the array declared by the `Index` node `node` to `env` and zeroes its elements.
+    fn declare_array(&mut self, node: &Node) {
+        let name = match &node.children[0].token {
+            Token::Ident(name) => name,
+            _ => panic!("no identifier in array declaration"),
+        };
+        let len = match const_value(&node.children[1]) {
+            Some(0) => unreachable!("rejected by check::check"),
+            Some(len) => len as usize,
+            None => unreachable!("rejected by check::check"),
+        };
+        let ty = Type::Array(len);
+        match self.env.get(name) {
+            Some(&Var { adr, ty: other }) if self.in_scope(name) && other == ty => {
+                for i in adr..adr + ty.size() {
+                    self.set(i, 0);
+                }
+            },
+            Some(_) if self.in_scope(name) => unreachable!("rejected by check::check"),
+            _ => {
+                self.declare(name, ty);
+            },
+        }
+    }
+
+    /// Looks up the array of the element `node`, which is an `Index` node. Returns the
+    /// address and length of the array, and the index if it is a constant.
+    fn element(&self, node: &Node) -> (usize, usize, Option<usize>) {
+        let name = match &node.children[0].token {
+            Token::Ident(name) => name,
+            _ => panic!("no identifier in array index"),
+        };
+        let (adr, len) = match self.env.get(name) {
+            Some(&Var { adr, ty: Type::Array(len) }) => (adr, len),
+            _ => unreachable!("rejected by check::check"),
+        };
+        let index = const_value(&node.children[1]).map(|index| index as usize);
+        if index.is_some_and(|index| index >= len) {
+            unreachable!("rejected by check::check");
+        }
+        (adr, len, index)
+    }
+
+    /// The address of the element at `index` in the array at `array`.
+    fn element_adr(array: usize, index: usize) -> usize {
+        array + 3 * index + 5
+    }
+
+    fn process_return(&mut self, node: &Node) {
+        let (result, alive) = match self.frames.last() {
+            Some(frame) => (frame.result, frame.alive),
+            None => unreachable!("rejected by check::check"),
+        };
+        self.process_expr_node(&node.children[0], result);
+        self.set(alive, 0);
+    }
+
+    /// Inlines a call to a user defined function and writes its return value to `result`.
+    fn process_call(&mut self, node: &Node, result: usize) {
+        let name = if let Token::Call(name) = &node.token {
+            name
+        } else { panic!("call node does not have the type Call") };
+        if self.frames.iter().any(|f| &f.name == name) {
+            unreachable!("recursive programs are compiled to a dispatch loop");
+        }
+        let func = match self.funcs.get(name) {
+            Some(func) => func.clone(),
+            None => unreachable!("rejected by check::check"),
+        };
+        let params = &func.children[1].children;
+        let body = &func.children[2];
+        if params.len() != node.children.len() {
+            unreachable!("rejected by check::check");
+        }
+
+        // arguments are evaluated in the caller's environment into fresh cells
+        let args: Vec<usize> = node.children
+            .iter()
+            .map(|arg| {
+                let adr = self.malloc(1);
+                self.process_expr_node(arg, adr);
+                adr
+            })
+            .collect();
+
+        // the body only sees its parameters
+        let caller_env = std::mem::take(&mut self.env);
+        for (param, adr) in params.iter().zip(args) {
+            if let Token::Ident(param) = &param.token {
+                self.env.insert(param.clone(), Var { adr, ty: Type::U8 });
+            }
+        }
+
+        let alive = self.malloc(1);
+        self.set(alive, 1);
+        self.set(result, 0);
+        self.frames.push(Frame { name: name.clone(), result, alive });
+        self.process_node(body);
+        self.frames.pop();
+        self.dealloc(alive);
+
+        // parameters and locals of the call are released
+        for (_, var) in std::mem::replace(&mut self.env, caller_env) {
+            self.dealloc(var.adr);
+        }
     }
 
     /// Parses and evaluates an expression to compute a value.
     /// Then writes that value to `result`, which is assumed to be zeroed.
+    /// Values wider than a cell are truncated.
     fn process_expr_node(&mut self, node: &Node, result: usize) {
+        self.process_expr_into(node, result, Type::U8);
+    }
+
+    /// Evaluates an expression and writes its value to `result` as a number of type `ty`,
+    /// truncating it if it doesn't fit. The expression is evaluated with at least as many
+    /// cells as `ty` has, so `var x: u16 = 200 + 100;` doesn't wrap around, and is signed
+    /// if `ty` is.
+    fn process_expr_into(&mut self, node: &Node, result: usize, ty: Type) {
+        let cells = ty.size();
+        let width = self.width_of(node).max(cells);
+        let signed = self.is_signed(node) || ty.is_signed();
+        if width == cells {
+            self.process_expr(node, result, width, signed);
+        } else {
+            let value = self.malloc(width);
+            self.process_expr(node, value, width, signed);
+            for i in 0..cells {
+                self.movval(value + i, result + i);
+            }
+            self.dealloc(value);
+        }
+    }
+
+    /// Evaluates an expression as a condition, so `result` is non zero if any cell
+    /// of its value is.
+    fn process_cond(&mut self, node: &Node, result: usize) {
+        let width = self.width_of(node);
+        let signed = self.is_signed(node);
+        if width == 1 {
+            self.process_expr(node, result, 1, signed);
+        } else {
+            let value = self.malloc(width);
+            self.process_expr(node, value, width, signed);
+            self.wide_bool(value, width, result);
+            self.dealloc(value);
+        }
+    }
+
+    /// The number of cells an expression is evaluated with, which is the size of
+    /// its widest variable or literal.
+    fn width_of(&self, node: &Node) -> usize {
+        node.children
+            .iter()
+            .map(|n| match &n.token {
+                Token::NumLit(val) => Type::of_literal(*val).size(),
+                Token::Ident(name) => match self.env.get(name) {
+                    Some(var) if var.ty.is_int() => var.ty.size(),
+                    _ => 1,
+                },
+                _ => 1,
+            })
+            .max()
+            .unwrap_or(1)
+    }
+
+    /// Checks if an expression is evaluated as signed, which it is if any of its variables is.
+    fn is_signed(&self, node: &Node) -> bool {
+        node.children.iter().any(|n| match &n.token {
+            Token::Ident(name) => self.env.get(name).is_some_and(|var| var.ty.is_signed()),
+            _ => false,
+        })
+    }
+
+    /// Evaluates an expression with `w` cells for every value, and writes the
+    /// result to the `w` cells at `result`. Comparisons and divisions treat the
+    /// values as two's complement numbers if `signed` is set.
+    fn process_expr(&mut self, node: &Node, result: usize, w: usize, signed: bool) {
         use Token::*;
         if node.token != Expr {
             panic!("expression node does not have the type Expr");
         }
+        let node = &fold_constants(node, w, signed);
 
-        let stack = self.malloc(node.children.len() / 2 + 1);
+        let stack = self.malloc((node.children.len() / 2 + 1) * w);
         let mut index = 0;
 
         for n in &node.children {
             // println!("stack at {}, ptr at {}", stack, self.ptr);
             // println!("env: {:?}, index: {}", self.env, index);
+            let top = stack + index * w;
+            let operands = match n.token {
+                BinOp(BiOp::And) | BinOp(BiOp::Or) | Not | Neg => 1,
+                BinOp(_) => 2,
+                _ => 0,
+            };
+            if index < operands {
+                self.errors.push(Diagnostic::error(n.span, format!("{} is missing an operand", n.token)));
+                self.dealloc(stack);
+                return;
+            }
+            // flipping the sign bits orders signed numbers the same way as unsigned ones
+            if let BinOp(op) = n.token {
+                if signed && is_ordering(op) {
+                    self.addconst(128, top - w - 1);
+                    self.addconst(128, top - 1);
+                }
+            }
             match &n.token {
                 NumLit(val) => {
-                    self.set(stack + index, *val);
+                    self.set_wide(top, *val, w);
+                    index += 1;
+                }
+                BoolLit(val) => {
+                    self.set_wide(top, *val as u32, w);
                     index += 1;
                 }
                 Ident(name) => {
-                    let adr = *self.env.get(name).expect("identifier in expression was not defined");
-                    self.cpy(adr, stack + index);
+                    let var = *self.env.get(name).unwrap_or_else(|| unreachable!("rejected by check::check"));
+                    if !var.ty.is_numeric() {
+                        unreachable!("rejected by check::check");
+                    }
+                    // narrower values are padded with zeroes, or ones if they are negative
+                    let size = var.ty.size().min(w);
+                    self.cpy_wide(var.adr, top, size);
+                    if var.ty.is_signed() && size < w {
+                        let flag = self.sign(top, size);
+                        self.set_wide(top + size, 0, w - size);
+                        self.mov(flag);
+                        self.out.push_str("[[-]");
+                        for i in top + size..top + w {
+                            self.subconst(1, i);
+                        }
+                        self.mov(flag);
+                        self.out.push(']');
+                        self.dealloc(flag);
+                    } else {
+                        self.set_wide(top + size, 0, w - size);
+                    }
                     index += 1;
                 },
-                BinOp(op) => {
+                Index => {
+                    match self.element(n) {
+                        (adr, _, Some(i)) => self.cpy(Self::element_adr(adr, i), top),
+                        (adr, len, None) => {
+                            let i = self.malloc(1);
+                            self.process_expr_node(&n.children[1], i);
+                            self.load(adr, len, i, top);
+                            self.dealloc(i);
+                        },
+                    }
+                    self.clear_upper(top, w);
+                    index += 1;
+                },
+                GetChar => {
+                    self.read_char(top);
+                    self.clear_upper(top, w);
+                    index += 1;
+                },
+                ReadNum => {
+                    self.read_decimal(top, w);
+                    index += 1;
+                },
+                GetLine => unreachable!("rejected by check::check"),
+                Call(_) => {
+                    self.process_call(n, top);
+                    self.clear_upper(top, w);
+                    index += 1;
+                },
+                BinOp(op @ BiOp::And) | BinOp(op @ BiOp::Or) => {
+                    self.process_logical(*op, top - w, w, &n.children[0]);
+                },
+                Not | Neg => {
+                    let operand = top - w;
+                    if n.token == Neg {
+                        self.wide_neg(operand, w);
+                    } else if w == 1 {
+                        self.not(operand);
+                    } else {
+                        let flag = self.malloc(1);
+                        self.wide_bool(operand, w, flag);
+                        self.not(flag);
+                        self.movval(flag, operand);
+                        self.dealloc(flag);
+                    }
+                },
+                BinOp(op) if signed && (*op == BiOp::Div || *op == BiOp::Mod) => {
+                    let a = top - 2 * w;
+                    let b = top - w;
+                    let quot = self.malloc(2 * w);
+                    let rem = quot + w;
+                    self.signed_divmod(b, a, quot, rem, w);
+                    let res = if *op == BiOp::Div { quot } else { rem };
+                    for i in 0..w {
+                        self.movval(res + i, a + i);
+                    }
+                    self.dealloc(quot);
+                    index -= 1;
+                },
+                BinOp(op) if w > 1 => {
                     use crate::token::BiOp::*;
 
-                    if index < 2 {
-                        eprintln!("Error: too many operators in expression node");
+                    let a = top - 2 * w;
+                    let b = top - w;
+                    match op {
+                        Add             => self.wide_add(b, a, w),
+                        Sub             => self.wide_sub(b, a, w),
+                        Mul             => self.wide_mul(b, a, w),
+                        Div | Mod => {
+                            let quot = self.malloc(2 * w);
+                            let rem = quot + w;
+                            self.wide_divmod(b, a, quot, rem, w);
+                            let res = if *op == Div { quot } else { rem };
+                            for i in 0..w {
+                                self.movval(res + i, a + i);
+                            }
+                            self.dealloc(quot);
+                        },
+                        Pow             => self.wide_pow(b, a, w),
+                        Equal | NotEqual | LessOrEqual | GreaterOrEqual | Less | Greater => {
+                            self.wide_compare(*op, a, b, w);
+                        },
+                        // handled above, since their right operand is nested
+                        And | Or        => unreachable!(),
+                        Invalid         => panic!("invalid operator"),
                     }
+                    index -= 1;
+                },
+                BinOp(op) => {
+                    use crate::token::BiOp::*;
 
-                    let a = stack + index - 2;
-                    let b = stack + index - 1;
+                    let a = top - 2;
+                    let b = top - 1;
                     match op {
                         Add             => self.consuming_add(b, a),
                         Sub             => self.consuming_sub(b, a),
                         Mul             => self.consuming_mul(b, a),
-                        Div             => todo!("division"),
-                        Pow             => todo!("exponent"),
+                        Div             => self.consuming_div(b, a),
+                        Mod             => self.consuming_mod(b, a),
+                        Pow             => self.consuming_pow(b, a),
                         Equal           => self.eq(b, a),
                         NotEqual        => self.neq(b, a),
                         // Negation because `geq` and `leq` writes result to the second address
@@ -130,68 +656,144 @@ impl Parser {
                         GreaterOrEqual  => self.leq(b, a),
                         Less            => self.gt(b, a),
                         Greater         => self.lt(b, a),
-                        //
+                        // handled above, since their right operand is nested
+                        And | Or        => unreachable!(),
                         Invalid         => panic!("invalid operator"),
                     }
                     index -= 1;
                 },
-                StrLit(_) => todo!("string literals in expressions"),
-                _ => eprintln!("Error: unexpected token in expression node"),
+                Len => {
+                    if n.children.len() != 1 {
+                        unreachable!("rejected by check::check");
+                    }
+                    let arg = &n.children[0];
+                    if let [Node { token: Ident(name), .. }] = arg.children.as_slice() {
+                        if let Some(Var { adr, ty: Type::Str(len) }) = self.env.get(name).copied() {
+                            self.str_len(adr, len, top);
+                            self.clear_upper(top, w);
+                            index += 1;
+                            continue;
+                        }
+                    }
+                    let (adr, len) = self.process_str_expr(arg);
+                    self.str_len(adr, len, top);
+                    self.dealloc(adr);
+                    self.clear_upper(top, w);
+                    index += 1;
+                },
+                StrLit(_) => unreachable!("rejected by check::check"),
+                _ => self.errors.push(Diagnostic::error(n.span, format!("unexpected {} in expression", n.token))),
             }
         }
 
         if index != 1 {
-            eprintln!("Error: too many numeric values in expression");
+            self.errors.push(Diagnostic::error(node.span, "expected an expression with a single value"));
         }
 
-        self.movval(stack, result);
+        for i in 0..w {
+            self.movval(stack + i, result + i);
+        }
         self.dealloc(stack);
     }
 
+    /// Computes `adr && rhs` or `adr || rhs`, where `adr` has `w` cells, and writes 0 or 1 to `adr`.
+    /// The expression `rhs` is only evaluated if `adr` doesn't decide the result.
+    fn process_logical(&mut self, op: BiOp, adr: usize, w: usize, rhs: &Node) {
+        // `lhs_flag` is set if `adr` was non zero and `rhs_flag` if `rhs` must be evaluated
+        let lhs_flag = self.malloc(2);
+        let rhs_flag = lhs_flag + 1;
+        if w == 1 {
+            self.movval(adr, lhs_flag);
+        } else {
+            self.wide_bool(adr, w, lhs_flag);
+        }
+        self.set(rhs_flag, (op == BiOp::Or) as num);
+
+        self.mov(lhs_flag);
+        self.out.push_str("[[-]");
+        if op == BiOp::Or {
+            self.set(adr, 1);
+            self.set(rhs_flag, 0);
+        } else {
+            self.set(rhs_flag, 1);
+        }
+        self.mov(lhs_flag);
+        self.out.push(']');
+
+        self.mov(rhs_flag);
+        self.out.push_str("[-");
+        self.process_cond(rhs, adr);
+        // a bool already is 0 or 1
+        if self.type_of(rhs) != Type::Bool {
+            self.make_bool(adr);
+        }
+        self.mov(rhs_flag);
+        self.out.push(']');
+
+        self.dealloc(lhs_flag);
+    }
+
     fn process_print(&mut self, node: &Node) {
         use Token::*;
-        let child_node = node.children.first().unwrap();
-        match &child_node.token {
-            StrLit(s) => {
-                let temp = self.calloc(1);
-                let mut last_c = 0_u8;
-                let mut chars = s.chars();
-                while let Some(c) = chars.next() {
-                    let c = if c == '\\' {
-                        match chars.next() {
-                            Some('n') => '\n',
-                            Some('r') => '\r',
-                            Some('t') => '\t',
-                            Some('"') => '\"',
-                            Some('\'') => '\'',
-                            Some('\\') => '\\',
-                            Some(esc) => esc,
-                            None => {
-                                eprintln!("Error: string literal ended with escape character '\\'");
-                                c
-                            }
-                        }
-                    } else { c };
-                    let c = c as u8;
-                    if c >= last_c {
-                        self.addconst(c - last_c, temp);
-                    } else {
-                        self.subconst(last_c - c, temp);
-                    }
-                    self.out.push('.');
-                    last_c = c;
-                }
-                self.dealloc(temp);
+        let expr = node.children.first().unwrap();
+        let ty = self.type_of(expr);
+        if node.token == PrintNum && ty == Type::Bool {
+            // a bool is a single digit
+            let result = self.malloc(1);
+            self.process_expr_node(expr, result);
+            self.addconst(b'0', result);
+            self.mov(result);
+            self.out.push('.');
+            self.dealloc(result);
+            return;
+        }
+        if node.token == PrintNum {
+            if !ty.is_int() {
+                unreachable!("rejected by check::check");
+            }
+            let width = self.width_of(expr);
+            let result = self.malloc(width);
+            // evaluated the way it would be if it was assigned to a variable of type `ty`
+            let signed = self.is_signed(expr) || ty.is_signed();
+            self.process_expr(expr, result, width, signed);
+            if ty.is_signed() {
+                // negative numbers are printed as a minus followed by their magnitude
+                let flag = self.sign(result, width);
+                self.mov(flag);
+                self.out.push_str("[[-]");
+                let minus = self.malloc(1);
+                self.set(minus, b'-');
+                self.out.push('.');
+                self.dealloc(minus);
+                self.wide_neg(result, width);
+                self.mov(flag);
+                self.out.push(']');
+                self.dealloc(flag);
+            }
+            self.print_decimal(result, width);
+            self.dealloc(result);
+            return;
+        }
+
+        match (ty, expr.children.as_slice()) {
+            (Type::Str(_), [Node { token: StrLit(s), .. }]) => self.print_literal(&unescape(s)),
+            (Type::Str(len), [Node { token: Ident(name), .. }]) => {
+                let adr = self.env[name].adr;
+                self.print_str(adr, len);
             },
-            Ident(_) => todo!(),
-            Expr => {
+            (Type::Str(_), _) => {
+                let (adr, len) = self.process_str_expr(expr);
+                self.print_str(adr, len);
+                self.dealloc(adr);
+            },
+            (Type::U8, _) | (Type::U16, _) | (Type::U32, _) | (Type::I8, _) | (Type::I16, _) | (Type::Bool, _) => {
                 let result = self.malloc(1);
-                self.process_expr_node(child_node, result);
+                self.process_expr_node(expr, result);
                 self.mov(result);
                 self.out.push('.');
                 self.dealloc(result);
             },
-            _ => unimplemented!(),
+            (Type::Array(_), _) => unreachable!("rejected by check::check"),
         }
 
         // let result = self.malloc(1);
@@ -201,21 +803,313 @@ impl Parser {
         // self.dealloc(result);
     }
 
+    /// Evaluates a string expression into newly alloc'd cells.
+    /// Returns their address and how many characters they can hold.
+    fn process_str_expr(&mut self, node: &Node) -> (usize, usize) {
+        use Token::*;
+        // every value is its address, its capacity, and whether its length
+        // is known to be equal to its capacity
+        let mut stack: Vec<(usize, usize, bool)> = Vec::new();
+        for n in &node.children {
+            match &n.token {
+                StrLit(s) => {
+                    let chars = unescape(s);
+                    let adr = self.malloc(chars.len() + 1);
+                    for (i, &c) in chars.iter().enumerate() {
+                        self.set(adr + i, c);
+                    }
+                    self.set(adr + chars.len(), 0);
+                    stack.push((adr, chars.len(), true));
+                },
+                Ident(name) => {
+                    let var = match self.env.get(name) {
+                        Some(&Var { adr, ty: Type::Str(len) }) => (adr, len),
+                        _ => unreachable!("rejected by check::check"),
+                    };
+                    let adr = self.malloc(var.1 + 1);
+                    for i in 0..=var.1 {
+                        self.cpy(var.0 + i, adr + i);
+                    }
+                    stack.push((adr, var.1, false));
+                },
+                GetLine => {
+                    let adr = self.malloc(LINE_SIZE + 1);
+                    self.read_line(adr, LINE_SIZE);
+                    stack.push((adr, LINE_SIZE, false));
+                },
+                BinOp(BiOp::Add) if stack.len() >= 2 => {
+                    let (b, b_len, b_exact) = stack.pop().unwrap();
+                    let (a, a_len, a_exact) = stack.pop().unwrap();
+                    let adr = self.malloc(a_len + b_len + 1);
+                    for i in 0..a_len {
+                        self.movval(a + i, adr + i);
+                    }
+                    for i in 0..=b_len {
+                        self.movval(b + i, adr + a_len + i);
+                    }
+                    if !a_exact {
+                        self.close_gap(adr, a_len, b_len);
+                    }
+                    self.dealloc(a);
+                    self.dealloc(b);
+                    stack.push((adr, a_len + b_len, a_exact && b_exact));
+                },
+                _ => unreachable!("rejected by check::check"),
+            }
+        }
+
+        match stack.as_slice() {
+            [(adr, len, _)] => (*adr, *len),
+            _ => panic!("malformed string expression"),
+        }
+    }
+
+    // Dispatch mode
+
+    /// Compiles the program as a dispatch loop, which is needed when functions are recursive.
+    ///
+    /// The program is split into basic blocks that are numbered from 1. The two `pc` cells hold
+    /// the number of the block to run next, and the whole program is a loop that runs the
+    /// block matching `pc` until it is 0. Every function gets fixed cells for its locals,
+    /// and a caller pushes its locals together with the block to return to onto a stack
+    /// before jumping to the callee, so that recursive calls don't clobber them.
+    fn process_dispatch(&mut self, root: &Node) {
+        let funcs = self.funcs.clone();
+        let mut lowering = Lowering::new(&funcs);
+        lowering.lower_function(None, &[], &root.children);
+        let mut names: Vec<&String> = funcs.keys().collect();
+        names.sort();
+        for name in names {
+            let func = &funcs[name];
+            lowering.lower_function(Some(name), &func.children[1].children, &func.children[2].children);
+        }
+        if lowering.blocks.len() > u16::MAX as usize {
+            self.errors.push(Diagnostic::error(root.span, format!(
+                "the program is split into {} blocks to support recursion, but at most {} are supported",
+                lowering.blocks.len(), u16::MAX
+            )));
+            return;
+        }
+
+        // every function, including the top level, gets fixed cells for its locals
+        let mut envs: HashMap<Option<String>, HashMap<String, Var>> = HashMap::new();
+        for (func, locals) in &lowering.locals {
+            let env = locals
+                .iter()
+                .map(|&(ref name, ty)| (name.clone(), Var { adr: self.malloc(ty.size()), ty }))
+                .collect();
+            envs.insert(func.clone(), env);
+        }
+
+        let pc = self.malloc(Self::PC_SIZE);
+        let retval = self.calloc(1);
+
+        // a call pushes the caller's locals and where to return to, which may start in
+        // the guard slot after the last one, so there are enough slots after it for them
+        let frame = lowering.locals
+            .iter()
+            .filter(|(func, _)| func.is_some())
+            .map(|(_, locals)| locals.iter().map(|(_, ty)| ty.size()).sum::<usize>())
+            .max()
+            .unwrap_or(0);
+        let size = 3 * (Self::STACK_SIZE + 1 + frame + Self::PC_SIZE);
+
+        // the stack is put after every other cell, so the pointer doesn't have to move across
+        // it all the time. That is only known once the blocks are compiled, so they are compiled
+        // once to find out, with the stack in front of the cells they use instead.
+        let (len, ptr, allocd, cells, errors) =
+            (self.out.len(), self.ptr, self.allocd.clone(), self.cells, self.errors.len());
+        let stack = self.malloc(size);
+        self.process_blocks(&lowering, &envs, pc, retval, stack);
+        let stack = self.cells - size;
+        self.out.truncate(len);
+        self.errors.truncate(errors);
+        (self.ptr, self.allocd, self.cells) = (ptr, allocd, cells);
+        self.allocd.insert(stack, size);
+        self.cells = self.cells.max(stack + size);
+
+        // the program hasn't touched any cells yet, so the stack is already zero
+        self.set(Self::stack_slot(stack, Self::STACK_SIZE + 1) + 2, 1);
+        self.process_blocks(&lowering, &envs, pc, retval, stack);
+    }
+
+    /// Compiles the blocks of `lowering` into a loop that runs the block with the id in the
+    /// `PC_SIZE` cells at `pc` until it is 0. `envs` has the cells of the locals of every function, `retval` is where
+    /// return values are passed and `stack` is the call stack.
+    fn process_blocks(
+        &mut self,
+        lowering: &Lowering,
+        envs: &HashMap<Option<String>, HashMap<String, Var>>,
+        pc: usize,
+        retval: usize,
+        stack: usize,
+    ) {
+        let running = self.malloc(1);
+        self.set_wide(pc, 1, Self::PC_SIZE);
+        self.set(running, 1);
+        self.out.push('[');
+        for (i, block) in lowering.blocks.iter().enumerate() {
+            let id = i + 1;
+            let locals: Vec<usize> = lowering.locals[&block.func]
+                .iter()
+                .flat_map(|(name, ty)| {
+                    let adr = envs[&block.func][name].adr;
+                    adr..adr + ty.size()
+                })
+                .collect();
+            self.env = envs[&block.func].clone();
+            // the locals already have cells, so declarations in the block reuse them
+            self.scopes = vec![self.env.keys().map(|name| (name.clone(), None)).collect()];
+
+            // run the block if every cell of `pc` is equal to that of its id
+            let flag = self.malloc(2);
+            self.set(flag, 0);
+            for i in 0..Self::PC_SIZE {
+                self.cpy(pc + i, flag + 1);
+                self.subconst((id >> (8 * i)) as num, flag + 1);
+                self.not(flag + 1);
+                self.consuming_add(flag + 1, flag);
+            }
+            self.subconst(Self::PC_SIZE as num, flag);
+            self.not(flag);
+            self.mov(flag);
+            self.out.push_str("[[-]");
+
+            // returning from a call restores the locals and fetches the return value
+            if let Some(result) = &block.resume {
+                if block.func.is_some() {
+                    self.pop(stack, &locals);
+                }
+                if let Some(result) = result {
+                    let adr = self.env[result].adr;
+                    self.movval(retval, adr);
+                }
+            }
+
+            self.process_statements(&block.statements, false);
+
+            match &block.exit {
+                Exit::Jump(next) => self.set_wide(pc, *next as u32, Self::PC_SIZE),
+                Exit::Branch(cond, then, other) => {
+                    let c = self.malloc(1);
+                    self.process_cond(cond, c);
+                    self.set_wide(pc, *other as u32, Self::PC_SIZE);
+                    self.mov(c);
+                    self.out.push_str("[[-]");
+                    self.set_wide(pc, *then as u32, Self::PC_SIZE);
+                    self.mov(c);
+                    self.out.push(']');
+                    self.dealloc(c);
+                },
+                Exit::Call { func, args, next } => {
+                    let params = &lowering.params[func];
+                    let callee = &envs[&Some(func.clone())];
+                    let params: Vec<usize> = params.iter().map(|p| callee[p].adr).collect();
+                    let args: Vec<usize> = args
+                        .iter()
+                        .map(|arg| {
+                            let adr = self.malloc(1);
+                            self.process_expr_node(arg, adr);
+                            adr
+                        })
+                        .collect();
+                    let overflow = self.calloc(1);
+                    if block.func.is_some() {
+                        self.push(stack, &locals, overflow);
+                    }
+                    let ret = self.malloc(Self::PC_SIZE);
+                    self.set_wide(ret, *next as u32, Self::PC_SIZE);
+                    let cells: Vec<usize> = (ret..ret + Self::PC_SIZE).collect();
+                    self.push(stack, &cells, overflow);
+                    self.dealloc(ret);
+                    for (arg, param) in args.into_iter().zip(params) {
+                        self.movval(arg, param);
+                        self.dealloc(arg);
+                    }
+                    self.set_wide(pc, lowering.entries[func] as u32, Self::PC_SIZE);
+
+                    // a call nested too deeply halts the program instead
+                    self.mov(overflow);
+                    self.out.push_str("[[-]");
+                    self.stack_overflow(pc);
+                    self.mov(overflow);
+                    self.out.push(']');
+                    self.dealloc(overflow);
+                },
+                Exit::Return(expr) => {
+                    self.process_expr_node(expr, retval);
+                    let cells: Vec<usize> = (pc..pc + Self::PC_SIZE).collect();
+                    self.pop(stack, &cells);
+                },
+                Exit::Halt => self.set_wide(pc, 0, Self::PC_SIZE),
+            }
+
+            self.mov(flag);
+            self.out.push(']');
+            self.dealloc(flag);
+        }
+        let temp = self.malloc(Self::PC_SIZE);
+        self.cpy_wide(pc, temp, Self::PC_SIZE);
+        self.wide_bool(temp, Self::PC_SIZE, running);
+        self.dealloc(temp);
+        self.mov(running);
+        self.out.push(']');
+        self.dealloc(running);
+    }
+
+    /// Checks if the function `name` can end up calling itself.
+    fn is_recursive(&self, name: &str) -> bool {
+        fn calls(node: &Node, found: &mut Vec<String>) {
+            if let Token::Call(name) = &node.token {
+                found.push(name.clone());
+            }
+            for n in &node.children {
+                calls(n, found);
+            }
+        }
+
+        let mut visited: Vec<String> = Vec::new();
+        let mut queue = vec![name.to_string()];
+        while let Some(current) = queue.pop() {
+            let mut found = Vec::new();
+            if let Some(func) = self.funcs.get(&current) {
+                calls(func, &mut found);
+            }
+            for callee in found {
+                if callee == name {
+                    return true;
+                }
+                if !visited.contains(&callee) {
+                    visited.push(callee.clone());
+                    queue.push(callee);
+                }
+            }
+        }
+        false
+    }
+
     // Internal functions
-    pub fn new() -> Self {
+    pub fn new(eof: EofMode) -> Self {
         Self {
             ptr:    0,
             env:    HashMap::new(),
+            scopes: Vec::new(),
             allocd: HashMap::new(),
+            funcs:  HashMap::new(),
+            frames: Vec::new(),
+            eof,
             out:    String::new(),
+            cells:  0,
+            errors: Vec::new(),
         }
     }
 
-    /// Alloc's memory for `size` contigous cells.
+    /// Alloc's memory for `size` contigous cells. Cells past `ARRAY_SIZE` are handed
+    /// out as well, and reported as an error once the program is compiled.
     fn malloc(&mut self, size: usize) -> usize {
         let mut adr = 0;
         let mut memlen = 0;
-        while adr < Self::ARRAY_SIZE {
+        loop {
             if self.allocd.contains_key(&adr) {
                 adr += self.allocd.get(&adr).unwrap();
                 memlen = 0;
@@ -224,12 +1118,12 @@ impl Parser {
                 memlen += 1;
             }
             if memlen == size {
-                adr = adr - size;
+                adr -= size;
                 self.allocd.insert(adr, size);
+                self.cells = self.cells.max(adr + size);
                 return adr;
             }
         }
-        panic!("out of memory");
     }
 
     /// As malloc, but zeroes all alloc'd cells.
@@ -244,49 +1138,406 @@ impl Parser {
     /// Dealloc's the alloc'd memory at `adr`.
     /// Panics if `adr` is not allocated.
     fn dealloc(&mut self, adr: usize) {
-        if let None = self.allocd.remove(&adr) {
+        if self.allocd.remove(&adr).is_none() {
             panic!("tried to deallocate non-alloc'd memory");
         }
     }
 
-    /// Assigns the value `val` to the variable name `name` 
-    /// and adds it to `env` if not already present.
-    fn assign(&mut self, name: &str) -> usize {
-        if let Some(temp) = self.env.get(name) {
-            *temp
-        } else {
-            let temp = self.calloc(1);
-            self.env.insert(name.to_string(), temp);
-            temp
+    /// Returns the variable `name` that is assigned a value of type `ty`. It is declared
+    /// if not already present, or if `declaration` is set and it was declared in an outer scope.
+    fn assign(&mut self, name: &str, ty: Type, declaration: bool) -> Var {
+        match self.env.get(name) {
+            Some(&var) if !declaration || self.in_scope(name) => {
+                if !var.ty.can_hold(ty) {
+                    unreachable!("rejected by check::check");
+                }
+                var
+            },
+            _ => self.declare(name, ty),
+        }
+    }
+
+    /// Adds the variable `name` of type `ty` to the innermost scope, shadowing any
+    /// variable with the same name until the scope ends.
+    fn declare(&mut self, name: &str, ty: Type) -> Var {
+        let var = Var { adr: self.calloc(ty.size()), ty };
+        let shadowed = self.env.insert(name.to_string(), var);
+        self.scopes
+            .last_mut()
+            .expect("variable declared outside of a scope")
+            .push((name.to_string(), shadowed));
+        var
+    }
+
+    /// Checks if `name` was declared in the innermost scope.
+    fn in_scope(&self, name: &str) -> bool {
+        self.scopes
+            .last()
+            .is_some_and(|scope| scope.iter().any(|(declared, _)| declared == name))
+    }
+
+    fn enter_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    /// Ends the innermost scope. The variables declared in it are freed, and the
+    /// ones they shadowed are visible again.
+    fn exit_scope(&mut self) {
+        let scope = self.scopes.pop().expect("no scope to exit");
+        for (name, shadowed) in scope.into_iter().rev() {
+            self.free(&name);
+            if let Some(var) = shadowed {
+                self.env.insert(name, var);
+            }
         }
     }
 
+    /// The type of the value `expr` evaluates to.
+    fn type_of(&self, expr: &Node) -> Type {
+        Type::of(expr, &|name| self.env.get(name).map(|var| var.ty))
+            .unwrap_or_else(|_| unreachable!("rejected by check::check"))
+    }
+
     /// Dealloc's a variable and removes it from `env`.
     fn free(&mut self, name: &str) {
-        if let Some(adr) = self.env.remove(name) {
-            self.dealloc(adr);
+        if let Some(var) = self.env.remove(name) {
+            self.dealloc(var.adr);
         } else {
             panic!("tried to free non alloc'd variable");
         }
     }
 
-    /// Moves the pointer to `adr`.
-    fn mov(&mut self, adr: usize) {
-        let dir = if self.ptr < adr { '>' } else { '<' };
-        let steps = (self.ptr as i32 - adr as i32).abs() as usize;
-        for _ in 0..steps {
-            self.out.push(dir);
+    /// Reads a line of at most `len` characters into the string at `adr`. The newline
+    /// or end of input that ends the line is replaced by a zero, and so are the cells after it.
+    fn read_line(&mut self, adr: usize, len: usize) {
+        // `alive` is 1 until the end of the line is read
+        let alive = self.malloc(3);
+        let flag = alive + 1;
+        let end = alive + 2;
+        self.set(alive, 1);
+
+        for i in adr..adr + len {
+            self.set(i, 0);
+            self.cpy(alive, flag);
+            self.mov(flag);
+            self.out.push_str("[[-]");
+            self.mov(i);
+            self.out.push(',');
+
+            // `end` is 1 if the character is a newline or marks the end of input
+            self.cpy(i, end);
+            self.subconst(b'\n', end);
+            self.not(end);
+            self.cpy(i, flag);
+            self.addconst(self.eof.value().wrapping_neg(), flag);
+            self.not(flag);
+            self.consuming_add(flag, end);
+
+            self.mov(end);
+            self.out.push_str("[[-]");
+            self.set(i, 0);
+            self.set(alive, 0);
+            self.mov(end);
+            self.out.push(']');
+            self.mov(flag);
+            self.out.push(']');
         }
-        self.ptr = adr;
+        self.set(adr + len, 0);
+
+        self.dealloc(alive);
     }
 
+    /// Reads a decimal number from the input into `adr`. Reading stops at the first
+    /// character that isn't a digit, which is consumed as well.
+    fn read_decimal(&mut self, adr: usize, w: usize) {
+        // `digit` is the value of the last character read, and `flag` is set if it's a digit
+        let digit = self.malloc(2 + w);
+        let flag = digit + 1;
+        let temp = digit + 2;
+        self.set_wide(adr, 0, w);
 
-    fn movval(&mut self, src: usize, dest: usize) {
-        self.mov(dest);
-        self.out.push_str("[-]");
-        self.mov(src);
+        self.read_digit(digit, flag);
+        self.mov(flag);
         self.out.push('[');
-        self.out.push('-');
+        self.set_wide(temp, 10, w);
+        self.wide_mul(temp, adr, w);
+        self.mov(digit);
+        self.out.push_str("[-");
+        self.wide_inc(adr, w);
+        self.mov(digit);
+        self.out.push(']');
+        self.read_digit(digit, flag);
+        self.mov(flag);
+        self.out.push(']');
+
+        self.dealloc(digit);
+    }
+
+    /// Reads a character into `adr`. End of input reads as 0, whatever `eof` is.
+    fn read_char(&mut self, adr: usize) {
+        // cleared first, so end of input reads as 0 if the cell is left unchanged
+        self.set(adr, 0);
+        self.out.push(',');
+        if self.eof.value() != 0 {
+            let flag = self.malloc(1);
+            self.cpy(adr, flag);
+            self.addconst(self.eof.value().wrapping_neg(), flag);
+            self.not(flag);
+            self.mov(flag);
+            self.out.push_str("[[-]");
+            self.set(adr, 0);
+            self.mov(flag);
+            self.out.push(']');
+            self.dealloc(flag);
+        }
+    }
+
+    /// Reads a character into `digit` as the digit it represents,
+    /// and sets `flag` to 1 if it is a digit and 0 otherwise.
+    fn read_digit(&mut self, digit: usize, flag: usize) {
+        self.set(digit, 0);
+        self.out.push(',');
+        self.subconst(b'0', digit);
+        self.set(flag, 10);
+        self.lt(digit, flag);
+    }
+
+    /// Prints the `w` cell number at `adr` as a decimal number, without leading zeroes.
+    /// Consumes the value.
+    fn print_decimal(&mut self, adr: usize, w: usize) {
+        let digits_len = mask(w).to_string().len();
+
+        // splits off the digits from the least significant one by dividing by 10
+        let digits = self.malloc(digits_len);
+        for i in (digits..digits + digits_len).rev() {
+            self.wide_divmod_small(adr, 10, i, w);
+        }
+
+        // `started` is set at the first non zero digit, the last digit is always printed
+        let started = self.calloc(2);
+        let flag = started + 1;
+        for i in digits..digits + digits_len {
+            if i == digits + digits_len - 1 {
+                self.set(started, 1);
+            } else {
+                self.cpy(i, flag);
+                self.mov(flag);
+                self.out.push_str("[[-]");
+                self.set(started, 1);
+                self.mov(flag);
+                self.out.push(']');
+            }
+            self.cpy(started, flag);
+            self.mov(flag);
+            self.out.push_str("[[-]");
+            self.addconst(b'0', i);
+            self.out.push('.');
+            self.mov(flag);
+            self.out.push(']');
+        }
+        self.dealloc(started);
+        self.dealloc(digits);
+    }
+
+    /// Counts the characters of the string at `adr`, which has room for `len`
+    /// characters, and writes the count to `result`.
+    fn str_len(&mut self, adr: usize, len: usize, result: usize) {
+        // every cell after the end of the string is zero, so counting non zero cells is enough
+        let flag = self.malloc(1);
+        self.set(result, 0);
+        for i in adr..adr + len {
+            self.cpy(i, flag);
+            self.make_bool(flag);
+            self.consuming_add(flag, result);
+        }
+        self.dealloc(flag);
+    }
+
+    /// Moves a string that was placed directly after the `len` cells of another
+    /// string at `adr` to the end of that string, when that one is shorter.
+    /// The moved string has room for `moved_len` characters.
+    fn close_gap(&mut self, adr: usize, len: usize, moved_len: usize) {
+        // every round moves the string one step if the cell before it is zero
+        let flag = self.malloc(1);
+        for pos in (adr..adr + len).rev() {
+            self.cpy(pos, flag);
+            self.not(flag);
+            self.mov(flag);
+            self.out.push_str("[[-]");
+            for i in pos..pos + moved_len {
+                self.movval(i + 1, i);
+            }
+            self.mov(flag);
+            self.out.push(']');
+        }
+        self.dealloc(flag);
+    }
+
+    /// Prints the characters `chars`, which are known at compile time.
+    fn print_literal(&mut self, chars: &[u8]) {
+        let temp = self.calloc(1);
+        let mut last_c = 0_u8;
+        for &c in chars {
+            if c >= last_c {
+                self.addconst(c - last_c, temp);
+            } else {
+                self.subconst(last_c - c, temp);
+            }
+            self.out.push('.');
+            last_c = c;
+        }
+        self.dealloc(temp);
+    }
+
+    /// Prints the zero terminated string at `adr`, which has room for `len` characters.
+    fn print_str(&mut self, adr: usize, len: usize) {
+        // `alive` is 1 until the terminating zero is reached
+        let alive = self.malloc(2);
+        let flag = alive + 1;
+        self.set(alive, 1);
+
+        for i in adr..adr + len {
+            self.cpy(i, flag);
+            self.mask(alive, flag);
+            self.mov(flag);
+            self.out.push_str("[[-]");
+            self.mov(i);
+            self.out.push('.');
+            self.mov(flag);
+            self.out.push(']');
+
+            self.cpy(i, flag);
+            self.make_bool(flag);
+            self.mask(alive, flag);
+            self.movval(flag, alive);
+        }
+
+        self.dealloc(alive);
+    }
+
+    /// Copies the element at the index in `index` of the array at `array` with `len`
+    /// elements to `dest`, and zeroes `index`. Indices out of bounds read as 0.
+    fn load(&mut self, array: usize, len: usize, index: usize, dest: usize) {
+        self.set(dest, 0);
+        let flag = self.in_bounds(index, len);
+        self.mov(flag);
+        self.out.push_str("[[-]");
+        // the index is counted down while it moves to the element, which is then
+        // copied and carried back until the marker in the first `d` cell is reached
+        self.set(array + 1, 1);
+        self.movval(index, array + 3);
+        self.mov(array + 3);
+        self.out.push_str("[-[->>>+<<<]>>>]");      // moves to the `c` cell of the element
+        self.out.push_str(">>[-<+<+>>]<[->+<]");    // copies the element to its `c` cell
+        self.out.push_str("-[+<[-<<<+>>>]<<-]+");   // carries it back to the first `c` cell
+        self.ptr = array + 1;
+        self.set(array + 1, 0);
+        self.movval(array, dest);
+        self.mov(flag);
+        self.out.push(']');
+        self.dealloc(flag);
+        self.set(index, 0);
+    }
+
+    /// Moves the value at `src` to the element at the index in `index` of the array
+    /// at `array` with `len` elements, and zeroes `index`. Indices out of bounds are
+    /// ignored.
+    fn store(&mut self, array: usize, len: usize, index: usize, src: usize) {
+        let flag = self.in_bounds(index, len);
+        self.mov(flag);
+        self.out.push_str("[[-]");
+        // the index and the value are carried to the element together, then
+        // the pointer moves back until the marker in the first `d` cell is reached
+        self.set(array + 1, 1);
+        self.movval(index, array + 3);
+        self.movval(src, array + 4);
+        self.mov(array + 3);
+        self.out.push_str("[-[->>>+<<<]>[->>>+<<<]>>]");  // moves to the `c` cell of the element
+        self.out.push_str(">>[-]<[->+<]");                  // moves the value into the element
+        self.out.push_str("-[+<<<-]+");                     // moves back to the first `d` cell
+        self.ptr = array + 1;
+        self.set(array + 1, 0);
+        self.mov(flag);
+        self.out.push(']');
+        self.dealloc(flag);
+        self.set(index, 0);
+        self.set(src, 0);
+    }
+
+    /// Returns a newly alloc'd cell that is 1 if the value at `index` is less than `len`, else 0.
+    fn in_bounds(&mut self, index: usize, len: usize) -> usize {
+        let flag = self.malloc(1);
+        if len > num::MAX as usize {
+            self.set(flag, 1);
+        } else {
+            self.set(flag, len as num);
+            self.lt(index, flag);
+        }
+        flag
+    }
+
+    /// The `u` cell of the slot `i` of the stack at `stack`. The stack holds `STACK_SIZE`
+    /// values in slots of three cells `[u t v]`, where `u` is 1 if the slot holds the value
+    /// `v`, and `t` is used to carry values to and from the top. Slot 0 is always empty, so
+    /// the pointer stops there when it moves back from the top. The slot after the last
+    /// one is a guard with a `v` of 1, which tells a push that the stack overflowed.
+    fn stack_slot(stack: usize, i: usize) -> usize {
+        stack + 3 * i
+    }
+
+    /// Pushes the values at `cells` onto the stack at `stack`, zeroing them. Adds 1 to
+    /// `overflow` if the stack overflows.
+    fn push(&mut self, stack: usize, cells: &[usize], overflow: usize) {
+        let first = Self::stack_slot(stack, 1);
+        for &cell in cells {
+            // the value is carried along the `t` cells to the first empty slot, and
+            // the `v` of the guard is carried back if that is where it ends up
+            self.movval(cell, first + 1);
+            self.mov(first);
+            self.out.push_str("[>[->>>+<<<]>>]");              // moves to the first empty slot
+            self.out.push_str(">>[-<<+>>]<[->+<]<[->+<]+");    // stores the value there
+            self.out.push_str("<<<[>>>>[-<<<+>>>]<<<<<<<]");   // carries the guard back to slot 1
+            self.ptr = stack;
+            self.consuming_add(first + 1, overflow);
+        }
+    }
+
+    /// Pops values from the stack at `stack` into `cells`, in the order they were pushed.
+    fn pop(&mut self, stack: usize, cells: &[usize]) {
+        let first = Self::stack_slot(stack, 1);
+        for &cell in cells.iter().rev() {
+            self.mov(first);
+            self.out.push_str("[>>>]<<<");                 // moves to the last full slot
+            self.out.push_str("->>[-<+>]<<<<<");            // empties it and takes the value
+            self.out.push_str("[>>>>[-<<<+>>>]<<<<<<<]");  // carries it back to slot 1
+            self.ptr = stack;
+            self.movval(first + 1, cell);
+        }
+    }
+
+    /// Prints that the stack overflowed and halts the program, by setting `pc` to 0.
+    fn stack_overflow(&mut self, pc: usize) {
+        self.print_literal(b"error: stack overflow, calls are nested too deeply\n");
+        self.set_wide(pc, 0, Self::PC_SIZE);
+    }
+
+    /// Moves the pointer to `adr`.
+    fn mov(&mut self, adr: usize) {
+        let dir = if self.ptr < adr { '>' } else { '<' };
+        let steps = (self.ptr as i32 - adr as i32).unsigned_abs();
+        for _ in 0..steps {
+            self.out.push(dir);
+        }
+        self.ptr = adr;
+    }
+
+
+    fn movval(&mut self, src: usize, dest: usize) {
+        self.mov(dest);
+        self.out.push_str("[-]");
+        self.mov(src);
+        self.out.push('[');
+        self.out.push('-');
         self.mov(dest);
         self.out.push('+');
         self.mov(src);
@@ -347,16 +1598,6 @@ impl Parser {
         self.out.push(']');
     }
 
-    /// Computes the difference between `lhs` and `rhs` and writes to `rhs`
-    /// (`rhs = rhs - lhs`).
-    fn sub(&mut self, lhs: usize, rhs: usize) {
-        let temp = self.malloc(1);
-        self.cpy(lhs, temp);
-        self.mov(temp);
-        self.consuming_sub(temp, rhs);
-        self.dealloc(temp);
-    }
-
     fn subconst(&mut self, c: num, adr: usize) {
         self.mov(adr);
         for _ in 0..c {
@@ -404,6 +1645,566 @@ impl Parser {
         self.dealloc(temp);
     }
 
+    /// Computes `rhs` to the power of `lhs` and writes to `rhs`.
+    ///
+    /// Consumes both operands, e.g. they are both unusable after this operation.
+    fn consuming_pow(&mut self, lhs: usize, rhs: usize) {
+        let base = self.malloc(1);
+        self.movval(rhs, base);
+        self.set(rhs, 1);
+        self.mov(lhs);
+        self.out.push_str("[-");
+        self.mul(base, rhs);
+        self.mov(lhs);
+        self.out.push(']');
+        self.dealloc(base);
+    }
+
+    /// Computes the quotient of `rhs` divided by `lhs` and writes to `rhs`.
+    ///
+    /// Consumes both operands, e.g. they are both unusable after this operation.
+    fn consuming_div(&mut self, lhs: usize, rhs: usize) {
+        let quot = self.malloc(2);
+        let rem = quot + 1;
+        self.divmod(lhs, rhs, quot, rem);
+        self.movval(quot, rhs);
+        self.dealloc(quot);
+    }
+
+    /// Computes the remainder of `rhs` divided by `lhs` and writes to `rhs`.
+    ///
+    /// Consumes both operands, e.g. they are both unusable after this operation.
+    fn consuming_mod(&mut self, lhs: usize, rhs: usize) {
+        let quot = self.malloc(2);
+        let rem = quot + 1;
+        self.divmod(lhs, rhs, quot, rem);
+        self.movval(rem, rhs);
+        self.dealloc(quot);
+    }
+
+    /// Divides `rhs` by `lhs` and writes the quotient to `quot` and the remainder to `rem`.
+    /// Consumes `rhs`.
+    ///
+    /// Counts `rem` up once for every unit in `rhs`, wrapping it to zero and counting `quot`
+    /// up whenever it reaches `lhs`. So dividing by zero gives a quotient of 0 and
+    /// leaves the dividend as the remainder.
+    fn divmod(&mut self, lhs: usize, rhs: usize, quot: usize, rem: usize) {
+        let flag = self.malloc(1);
+        self.set(quot, 0);
+        self.set(rem, 0);
+
+        self.mov(rhs);
+        self.out.push_str("[-");
+        self.addconst(1, rem);
+        self.cpy(lhs, flag);
+        self.eq(rem, flag);
+        self.mov(flag);
+        self.out.push_str("[[-]");
+        self.set(rem, 0);
+        self.addconst(1, quot);
+        self.mov(flag);
+        self.out.push(']');
+        self.mov(rhs);
+        self.out.push(']');
+
+        self.dealloc(flag);
+    }
+
+    // Multi-cell numbers
+    //
+    // Numbers wider than one cell are stored with their least significant byte first.
+    // The routines below take the number of cells `w` of their operands, and use the
+    // single cell routines above when `w` is 1.
+
+    /// Sets the `w` cells at `dest` to `val`, dropping the bytes that don't fit.
+    fn set_wide(&mut self, dest: usize, val: u32, w: usize) {
+        for i in 0..w {
+            let byte = val.checked_shr(8 * i as u32).unwrap_or(0);
+            self.set(dest + i, byte as num);
+        }
+    }
+
+    /// Zeroes all but the first of the `w` cells at `adr`, so a single cell value
+    /// written there reads as a `w` cell number.
+    fn clear_upper(&mut self, adr: usize, w: usize) {
+        for i in 1..w {
+            self.set(adr + i, 0);
+        }
+    }
+
+    /// Copies the `w` cells at `src` to `dest`.
+    fn cpy_wide(&mut self, src: usize, dest: usize, w: usize) {
+        for i in 0..w {
+            self.cpy(src + i, dest + i);
+        }
+    }
+
+    /// Adds 1 to the `w` cell number at `adr`, carrying into the higher cells.
+    fn wide_inc(&mut self, adr: usize, w: usize) {
+        self.addconst(1, adr);
+        if w == 1 {
+            return;
+        }
+        // every cell is only incremented if the one below it wrapped around to 0
+        let flag = self.malloc(1);
+        for i in 1..w {
+            self.cpy(adr + i - 1, flag);
+            self.not(flag);
+            self.mov(flag);
+            self.out.push_str("[[-]");
+            self.addconst(1, adr + i);
+        }
+        for _ in 1..w {
+            self.mov(flag);
+            self.out.push(']');
+        }
+        self.dealloc(flag);
+    }
+
+    /// Subtracts 1 from the `w` cell number at `adr`, borrowing from the higher cells.
+    fn wide_dec(&mut self, adr: usize, w: usize) {
+        if w == 1 {
+            self.subconst(1, adr);
+            return;
+        }
+        // every cell is only decremented if the one below it was 0
+        let flag = self.malloc(1);
+        for i in 0..w {
+            if i + 1 < w {
+                self.cpy(adr + i, flag);
+                self.not(flag);
+            }
+            self.subconst(1, adr + i);
+            if i + 1 < w {
+                self.mov(flag);
+                self.out.push_str("[[-]");
+            }
+        }
+        for _ in 1..w {
+            self.mov(flag);
+            self.out.push(']');
+        }
+        self.dealloc(flag);
+    }
+
+    /// As `consuming_add`, for `w` cell numbers.
+    fn wide_add(&mut self, lhs: usize, rhs: usize, w: usize) {
+        if w == 1 {
+            self.consuming_add(lhs, rhs);
+            return;
+        }
+        // adds cell by cell from the least significant one, `carry` is set if the
+        // cell below wrapped around
+        let carry = self.calloc(2);
+        let temp = carry + 1;
+        for i in 0..w {
+            if i > 0 {
+                // adding the carry only wraps if the cell becomes 0
+                self.set(temp, 0);
+                self.mov(carry);
+                self.out.push_str("[-");
+                self.addconst(1, rhs + i);
+                self.cpy(rhs + i, temp);
+                self.not(temp);
+                self.mov(carry);
+                self.out.push(']');
+                self.movval(temp, carry);
+            }
+            if i + 1 < w {
+                // adding `lhs` wraps if the sum is less than it
+                self.cpy(lhs + i, temp);
+                self.consuming_add(lhs + i, rhs + i);
+                self.lt(rhs + i, temp);
+                self.consuming_add(temp, carry);
+            } else {
+                self.consuming_add(lhs + i, rhs + i);
+            }
+        }
+        self.dealloc(carry);
+    }
+
+    /// As `consuming_sub`, for `w` cell numbers.
+    fn wide_sub(&mut self, lhs: usize, rhs: usize, w: usize) {
+        if w == 1 {
+            self.consuming_sub(lhs, rhs);
+            return;
+        }
+        // subtracts cell by cell from the least significant one, `borrow` is set if the
+        // cell below wrapped around
+        let borrow = self.calloc(2);
+        let temp = borrow + 1;
+        for i in 0..w {
+            if i > 0 {
+                // subtracting the borrow only wraps if the cell was 0
+                self.set(temp, 0);
+                self.mov(borrow);
+                self.out.push_str("[-");
+                self.cpy(rhs + i, temp);
+                self.not(temp);
+                self.subconst(1, rhs + i);
+                self.mov(borrow);
+                self.out.push(']');
+                self.movval(temp, borrow);
+            }
+            if i + 1 < w {
+                // subtracting `lhs` wraps if the cell is less than it
+                self.cpy(lhs + i, temp);
+                self.lt(rhs + i, temp);
+                self.consuming_add(temp, borrow);
+            }
+            self.consuming_sub(lhs + i, rhs + i);
+        }
+        self.dealloc(borrow);
+    }
+
+    /// As `consuming_mul`, for `w` cell numbers.
+    fn wide_mul(&mut self, lhs: usize, rhs: usize, w: usize) {
+        if w == 1 {
+            self.consuming_mul(lhs, rhs);
+            return;
+        }
+        // shift and add over the bits of `lhs` from the most significant one, the product
+        // is doubled for every bit and `rhs` is added to it if the bit is set
+        let prod = self.calloc(2 * w + 2);
+        let temp = prod + w;
+        let flag = temp + w;
+        let count = flag + 1;
+        self.set(count, 8 * w as num);
+        self.out.push('[');
+        self.wide_double(prod, w);
+        self.set(flag, 128);
+        self.geq(lhs + w - 1, flag);
+        self.wide_double(lhs, w);
+        self.mov(flag);
+        self.out.push_str("[-");
+        self.cpy_wide(rhs, temp, w);
+        self.wide_add(temp, prod, w);
+        self.mov(flag);
+        self.out.push(']');
+        self.subconst(1, count);
+        self.out.push(']');
+        for i in 0..w {
+            self.movval(prod + i, rhs + i);
+        }
+        self.dealloc(prod);
+    }
+
+    /// As `consuming_pow`, for `w` cell numbers.
+    fn wide_pow(&mut self, lhs: usize, rhs: usize, w: usize) {
+        if w == 1 {
+            self.consuming_pow(lhs, rhs);
+            return;
+        }
+        let base = self.malloc(2 * w + 1);
+        let temp = base + w;
+        let flag = base + 2 * w;
+        for i in 0..w {
+            self.movval(rhs + i, base + i);
+        }
+        self.set_wide(rhs, 1, w);
+
+        self.cpy_wide(lhs, temp, w);
+        self.wide_bool(temp, w, flag);
+        self.mov(flag);
+        self.out.push('[');
+        self.wide_dec(lhs, w);
+        self.cpy_wide(base, temp, w);
+        self.wide_mul(temp, rhs, w);
+        self.cpy_wide(lhs, temp, w);
+        self.wide_bool(temp, w, flag);
+        self.mov(flag);
+        self.out.push(']');
+
+        self.dealloc(base);
+    }
+
+    /// Returns a newly alloc'd cell that is 1 if the `w` cell two's complement number
+    /// at `adr` is negative, else 0.
+    fn sign(&mut self, adr: usize, w: usize) -> usize {
+        let flag = self.malloc(1);
+        self.set(flag, 128);
+        self.geq(adr + w - 1, flag);
+        flag
+    }
+
+    /// As `wide_divmod`, for two's complement numbers. The quotient is rounded towards
+    /// zero, and the remainder has the sign of the dividend.
+    fn signed_divmod(&mut self, lhs: usize, rhs: usize, quot: usize, rem: usize, w: usize) {
+        // `rhs_neg` and `lhs_neg` are set if the operands are negative, and they are
+        // made positive, so the quotient is negated if exactly one of them was
+        let rhs_neg = self.sign(rhs, w);
+        let lhs_neg = self.sign(lhs, w);
+        let quot_neg = self.malloc(1);
+        for (neg, adr) in [(rhs_neg, rhs), (lhs_neg, lhs)] {
+            self.cpy(neg, quot_neg);
+            self.mov(quot_neg);
+            self.out.push_str("[[-]");
+            self.wide_neg(adr, w);
+            self.mov(quot_neg);
+            self.out.push(']');
+        }
+        self.wide_divmod(lhs, rhs, quot, rem, w);
+
+        self.cpy(rhs_neg, quot_neg);
+        self.neq(lhs_neg, quot_neg);
+        for (neg, adr) in [(quot_neg, quot), (rhs_neg, rem)] {
+            self.mov(neg);
+            self.out.push_str("[[-]");
+            self.wide_neg(adr, w);
+            self.mov(neg);
+            self.out.push(']');
+        }
+        self.dealloc(quot_neg);
+        self.dealloc(lhs_neg);
+        self.dealloc(rhs_neg);
+    }
+
+    /// Doubles the `w` cell number at `adr`.
+    fn wide_double(&mut self, adr: usize, w: usize) {
+        // doubles the cells from the most significant one, so the top bit of a cell
+        // can be added to the already doubled cell above it, whose lowest bit is 0
+        let temp = self.malloc(2);
+        let flag = temp + 1;
+        for i in (0..w).rev() {
+            if i + 1 < w {
+                self.set(flag, 128);
+                self.geq(adr + i, flag);
+            }
+            self.movval(adr + i, temp);
+            self.mov(temp);
+            self.out.push_str("[-");
+            self.addconst(2, adr + i);
+            self.mov(temp);
+            self.out.push(']');
+            if i + 1 < w {
+                self.consuming_add(flag, adr + i + 1);
+            }
+        }
+        self.dealloc(temp);
+    }
+
+    /// Divides the `w` cell number at `adr` by the constant `d` in place, and writes the
+    /// remainder to `rem`. `d` is at most 16, so the intermediate values fit in a cell.
+    fn wide_divmod_small(&mut self, adr: usize, d: num, rem: usize, w: usize) {
+        // short division from the most significant cell, where a cell `c` with the
+        // remainder `r` of the cells above it is `r * 256 + c`, and with `c = d * qc + rc`
+        // and `256 = d * k + m` that is `d * (r * k + qc) + (r * m + rc)`
+        let k = (256 / d as usize) as num;
+        let m = (256 % d as usize) as num;
+        let div = self.malloc(4);
+        let quot = div + 1;
+        let r = div + 2;
+        let temp = div + 3;
+        self.set(div, d);
+        self.set(rem, 0);
+        for i in (0..w).rev() {
+            if i + 1 == w {
+                self.divmod(div, adr + i, quot, rem);
+                self.movval(quot, adr + i);
+                continue;
+            }
+            self.divmod(div, adr + i, quot, r);
+            self.movval(quot, adr + i);
+            self.cpy(rem, temp);
+            self.mov(temp);
+            self.out.push_str("[-");
+            self.addconst(k, adr + i);
+            self.mov(temp);
+            self.out.push(']');
+            self.mov(rem);
+            self.out.push_str("[-");
+            self.addconst(m, r);
+            self.mov(rem);
+            self.out.push(']');
+            self.divmod(div, r, quot, rem);
+            self.consuming_add(quot, adr + i);
+        }
+        self.dealloc(div);
+    }
+
+    /// As `divmod`, for `w` cell numbers, so dividing by zero gives a quotient of 0
+    /// and leaves the dividend as the remainder.
+    fn wide_divmod(&mut self, lhs: usize, rhs: usize, quot: usize, rem: usize, w: usize) {
+        if w == 1 {
+            self.divmod(lhs, rhs, quot, rem);
+            return;
+        }
+        // long division, which shifts the bits of the dividend into the remainder one at a time,
+        // starting at the most significant bit. The dividend, remainder and divisor get an
+        // extra cell, so the top bit of the dividend and the doubled remainder don't overflow.
+        let dividend = self.malloc(3 * (w + 1) + 1);
+        let r = dividend + w + 1;
+        let divisor = r + w + 1;
+        let count = divisor + w + 1;
+        for i in 0..w {
+            self.movval(rhs + i, dividend + i);
+        }
+        self.set(dividend + w, 0);
+        self.set_wide(r, 0, w + 1);
+        self.cpy_wide(lhs, divisor, w);
+        self.set(divisor + w, 0);
+        self.set_wide(quot, 0, w);
+
+        self.set(count, 8 * w as num);
+        self.mov(count);
+        self.out.push('[');
+        self.wide_double(dividend, w + 1);
+        self.wide_double(r, w + 1);
+        // the bit shifted out of the dividend is added to the remainder, whose lowest bit is 0
+        self.consuming_add(dividend + w, r);
+        self.wide_double(quot, w);
+        let flag = self.wide_geq(r, divisor, w + 1);
+        self.mov(flag);
+        self.out.push_str("[[-]");
+        let temp = self.malloc(w + 1);
+        self.cpy_wide(divisor, temp, w + 1);
+        self.wide_sub(temp, r, w + 1);
+        self.dealloc(temp);
+        self.addconst(1, quot);
+        self.mov(flag);
+        self.out.push(']');
+        self.dealloc(flag);
+        self.subconst(1, count);
+        self.out.push(']');
+
+        // every step subtracted 0 when dividing by 0, so every bit of the quotient is set
+        let flag = self.malloc(1);
+        self.wide_bool(divisor, w + 1, flag);
+        self.not(flag);
+        self.mov(flag);
+        self.out.push_str("[[-]");
+        self.set_wide(quot, 0, w);
+        self.mov(flag);
+        self.out.push(']');
+        self.dealloc(flag);
+
+        for i in 0..w {
+            self.movval(r + i, rem + i);
+        }
+        self.dealloc(dividend);
+    }
+
+    /// As `neg`, for `w` cell numbers.
+    fn wide_neg(&mut self, adr: usize, w: usize) {
+        if w == 1 {
+            self.neg(adr);
+            return;
+        }
+        // inverts every bit, then adds 1
+        for i in 0..w {
+            self.neg(adr + i);
+            self.subconst(1, adr + i);
+        }
+        self.wide_inc(adr, w);
+    }
+
+    /// Writes 1 to `result` if any of the `w` cells at `adr` is non zero, and 0 otherwise.
+    /// Consumes the cells.
+    fn wide_bool(&mut self, adr: usize, w: usize, result: usize) {
+        self.set(result, 0);
+        for i in adr..adr + w {
+            self.mov(i);
+            self.out.push_str("[[-]");
+            self.set(result, 1);
+            self.mov(i);
+            self.out.push(']');
+        }
+    }
+
+    /// Returns a newly alloc'd cell that is 1 if the `w` cell number at `lhs` is
+    /// greater or equal to the one at `rhs`, else 0.
+    fn wide_geq(&mut self, lhs: usize, rhs: usize, w: usize) -> usize {
+        // the difference gets an extra cell, which only becomes non zero if it borrows
+        let diff = self.malloc(2 * (w + 1));
+        let temp = diff + w + 1;
+        self.cpy_wide(lhs, diff, w);
+        self.set(diff + w, 0);
+        self.cpy_wide(rhs, temp, w);
+        self.set(temp + w, 0);
+        self.wide_sub(temp, diff, w + 1);
+
+        let flag = self.malloc(1);
+        self.movval(diff + w, flag);
+        self.not(flag);
+        self.dealloc(diff);
+        flag
+    }
+
+    /// Returns a newly alloc'd cell that is 1 if the `w` cell numbers at `lhs` and
+    /// `rhs` are equal, else 0.
+    fn wide_eq(&mut self, lhs: usize, rhs: usize, w: usize) -> usize {
+        let diff = self.malloc(2 * w);
+        let temp = diff + w;
+        self.cpy_wide(lhs, diff, w);
+        self.cpy_wide(rhs, temp, w);
+        self.wide_sub(temp, diff, w);
+
+        let flag = self.malloc(1);
+        self.wide_bool(diff, w, flag);
+        self.not(flag);
+        self.dealloc(diff);
+        flag
+    }
+
+    /// Compares the `w` cell numbers at `lhs` and `rhs` with the comparator `op`,
+    /// and writes 0 or 1 to `lhs`.
+    fn wide_compare(&mut self, op: BiOp, lhs: usize, rhs: usize, w: usize) {
+        use crate::token::BiOp::*;
+        let flag = match op {
+            Equal | NotEqual    => self.wide_eq(lhs, rhs, w),
+            LessOrEqual         => self.wide_geq(rhs, lhs, w),
+            GreaterOrEqual      => self.wide_geq(lhs, rhs, w),
+            Less                => self.wide_geq(lhs, rhs, w),
+            Greater             => self.wide_geq(rhs, lhs, w),
+            _ => panic!("`{:?}` is not a comparator", op),
+        };
+        if let NotEqual | Less | Greater = op {
+            self.not(flag);
+        }
+        self.set_wide(lhs, 0, w);
+        self.movval(flag, lhs);
+        self.dealloc(flag);
+    }
+
+    /// Sets `adr` to the value at `mask` if `adr` is non zero.
+    fn mask(&mut self, mask: usize, adr: usize) {
+        let temp = self.calloc(1);
+
+        self.mov(adr);
+        self.out.push_str("[[-]");
+        self.mov(temp);
+        self.out.push('+');
+        self.mov(adr);
+        self.out.push(']');
+
+        self.mov(temp);
+        self.out.push_str("[-");
+        self.cpy(mask, adr);
+        self.mov(temp);
+        self.out.push(']');
+
+        self.dealloc(temp);
+    }
+
+    /// Negates the value at `adr`, wrapping around so that `x + -x` is 0.
+    fn neg(&mut self, adr: usize) {
+        let temp = self.malloc(1);
+        self.movval(adr, temp);
+        self.mov(temp);
+        self.out.push_str("[-");
+        self.mov(adr);
+        self.out.push('-');
+        self.mov(temp);
+        self.out.push(']');
+        self.dealloc(temp);
+    }
+
+    /// Writes a 1 to `adr` if it's non zero, and 0 otherwise.
+    fn make_bool(&mut self, adr: usize) {
+        self.not(adr);
+        self.not(adr);
+    }
+
     /// Writes a 1 to `adr` if it's zero, and 0 otherwise. Assumes wrapping.
     fn not(&mut self, adr: usize) {
         let temp = self.calloc(1);
@@ -451,23 +2252,24 @@ impl Parser {
     /// Tests if `lhs` is greater or equal to `rhs`.
     /// Will set `rhs` to either 1 or 0.
     fn geq(&mut self, lhs: usize, rhs: usize) {
+        // computed as `!(rhs > lhs)`, so that `lhs = 255` needs no special case
         //                      v
-        // initial layout: [0 1 0 a b 0]
+        // initial layout: [1 1 0 b a 0]
         let result = self.malloc(6);
-        let a = result + 3;
-        let b = result + 4;
+        let b = result + 3;
+        let a = result + 4;
 
-        self.set(result, 0);
+        self.set(result, 1);
         self.set(result + 1, 1);
         self.set(result + 2, 0);
-        self.cpy(lhs, a);
         self.cpy(rhs, b);
+        self.cpy(lhs, a);
         self.set(result + 5, 0);
 
-        self.mov(a);
-        self.out.push_str("+>+<");          // to handle the cases `a=0` and `b=0`
-        self.out.push_str("[->-[>]<<]<");   // ends on `result + 1` if `a>=b`, else `result + 2`
-        self.out.push_str("[<+>>]<<");      // sets `result` to 1 if `a>=b` and moves to result
+        self.mov(b);
+        self.out.push_str(">+<");           // to handle the case `a=0`
+        self.out.push_str("[->-[>]<<]<");   // ends on `result + 1` if `b>a`, else `result + 2`
+        self.out.push_str("[<->>]<<");      // clears `result` if `b>a` and moves to result
         self.ptr = result;                  // set `ptr` manually
         self.movval(result, rhs);           // writes `result` to `rhs`
         self.dealloc(result);               // at end array is [result 1 0 X X 0]
@@ -488,6 +2290,7 @@ impl Parser {
         self.cpy(lhs, temp);
         self.geq(rhs, temp);
         self.movval(temp, rhs);
+        self.dealloc(temp);
     }
 
     /// Tests if `lhs` is less than `rhs`.
@@ -499,4 +2302,412 @@ impl Parser {
         self.not(rhs);              // negates the result
     }
 }
- 
\ No newline at end of file
+ 
+
+/// The type given in a declaration like `var x: u16 = 0;`, if any.
+fn declared_type(node: &Node) -> Option<Type> {
+    match node.children.get(2) {
+        Some(Node { token: Token::TypeName(name), .. }) => match Type::named(name) {
+            Some(ty) => Some(ty),
+            None => unreachable!("rejected by check::check"),
+        },
+        _ => None,
+    }
+}
+
+/// Checks if `node` or any node below it is a call to a user defined function.
+fn contains_call(node: &Node) -> bool {
+    matches!(node.token, Token::Call(_)) || node.children.iter().any(contains_call)
+}
+
+/// The index in the expression `rpn`, which is in reverse polish notation, where
+/// the operands of its last value start.
+fn last_value_start(rpn: &[Node]) -> usize {
+    let mut missing = 1;
+    for (i, n) in rpn.iter().enumerate().rev() {
+        match n.token {
+            // the right side of `&&` and `||` is a child, so like `!` they take one operand
+            Token::BinOp(BiOp::And | BiOp::Or) | Token::Not | Token::Neg => (),
+            Token::BinOp(_) => missing += 1,
+            _ => missing -= 1,
+        }
+        if missing == 0 {
+            return i;
+        }
+    }
+    0
+}
+
+/// Checks if `node` or any node below it is a `return` statement.
+fn contains_return(node: &Node) -> bool {
+    node.token == Token::Return || node.children.iter().any(contains_return)
+}
+
+/// A straight line of statements without calls, followed by a jump to other blocks.
+struct BasicBlock {
+    /// The function the block is part of, or `None` for the top level.
+    func:       Option<String>,
+    /// Set if the block is returned to after a call. It then holds the variable
+    /// the return value is assigned to, if any.
+    resume:     Option<Option<String>>,
+    statements: Vec<Node>,
+    exit:       Exit,
+}
+
+/// Where to go after a basic block. Block ids start at 1, 0 halts the program.
+enum Exit {
+    Jump(usize),
+    /// Jumps to the first block if the condition is non zero, else to the second.
+    Branch(Node, usize, usize),
+    Call { func: String, args: Vec<Node>, next: usize },
+    Return(Node),
+    Halt,
+}
+
+/// Splits a program into basic blocks for dispatch mode. Calls are hoisted out of
+/// expressions into temporary variables, `&&` and `||` with calls on their right are
+/// split into branches, and variables declared in a `for` loop are renamed so they
+/// don't clash with the rest of the function.
+struct Lowering<'a> {
+    funcs:   &'a HashMap<String, Node>,
+    blocks:  Vec<BasicBlock>,
+    /// Local variables (including parameters and temporaries) of every function.
+    locals:  HashMap<Option<String>, Vec<(String, Type)>>,
+    params:  HashMap<String, Vec<String>>,
+    entries: HashMap<String, usize>,
+    scopes:  Vec<HashMap<String, String>>,
+    func:    Option<String>,
+    temps:   usize,
+}
+
+impl<'a> Lowering<'a> {
+    fn new(funcs: &'a HashMap<String, Node>) -> Self {
+        Self {
+            funcs,
+            blocks:  Vec::new(),
+            locals:  HashMap::new(),
+            params:  HashMap::new(),
+            entries: HashMap::new(),
+            scopes:  Vec::new(),
+            func:    None,
+            temps:   0,
+        }
+    }
+
+    fn lower_function(&mut self, name: Option<&String>, params: &[Node], body: &[Node]) {
+        self.func = name.cloned();
+        self.scopes = vec![HashMap::new()];
+        self.locals.insert(self.func.clone(), Vec::new());
+
+        let params: Vec<String> = params
+            .iter()
+            .filter_map(|p| if let Token::Ident(p) = &p.token { Some(p.clone()) } else { None })
+            .collect();
+        for p in &params {
+            self.declare(p, Type::U8);
+        }
+
+        let mut cur = self.new_block(None);
+        if let Some(name) = name {
+            self.params.insert(name.clone(), params);
+            self.entries.insert(name.clone(), cur);
+        }
+        self.lower_statements(body, &mut cur);
+        self.block(cur).exit = if name.is_some() {
+            Exit::Return(Node::new(Token::Expr, vec![Node::leaf(Token::NumLit(0))]))
+        } else {
+            Exit::Halt
+        };
+    }
+
+    fn lower_statements(&mut self, statements: &[Node], cur: &mut usize) {
+        use Token::*;
+        for n in statements {
+            match &n.token {
+                Branch => {
+                    let cond = self.hoist(&n.children[0], cur);
+                    let then = self.new_block(None);
+                    let other = self.new_block(None);
+                    let join = self.new_block(None);
+                    self.block(*cur).exit = Exit::Branch(cond, then, other);
+
+                    *cur = then;
+                    self.lower_block(&n.children[1], cur);
+                    self.block(*cur).exit = Exit::Jump(join);
+                    *cur = other;
+                    self.lower_block(&n.children[2], cur);
+                    self.block(*cur).exit = Exit::Jump(join);
+                    *cur = join;
+                },
+                While => self.lower_loop(&n.children[0], &n.children[1], None, cur),
+                For => {
+                    self.scopes.push(HashMap::new());
+                    self.lower_statements(std::slice::from_ref(&n.children[0]), cur);
+                    self.lower_loop(&n.children[1], &n.children[3], Some(&n.children[2]), cur);
+                    self.scopes.pop();
+                },
+                VarSig if n.children.len() == 1 => {
+                    let array = &n.children[0];
+                    let len = match const_value(&array.children[1]) {
+                        Some(len) => len as usize,
+                        None => unreachable!("rejected by check::check"),
+                    };
+                    let name = match &array.children[0].token {
+                        Ident(name) => self.local(name, Type::Array(len), true),
+                        _ => panic!("no identifier in array declaration"),
+                    };
+                    let stmt = Node::new(Index, vec![Node::leaf(Ident(name)), array.children[1].clone()]);
+                    self.block(*cur).statements.push(Node::new(VarSig, vec![stmt]));
+                },
+                VarSig | Assign => {
+                    let expr = self.hoist(&n.children[1], cur);
+                    let target = match &n.children[0] {
+                        target @ Node { token: Index, .. } => self.hoist_index(target, cur),
+                        Node { token: Ident(name), .. } => {
+                            let locals = &self.locals[&self.func];
+                            let ty = declared_type(n).unwrap_or_else(|| Type::of(&expr, &|name| {
+                                locals.iter().find(|(local, _)| local == name).map(|&(_, ty)| ty)
+                            }).unwrap_or_else(|_| unreachable!("rejected by check::check")));
+                            Node::leaf(Ident(self.local(name, ty, n.token == VarSig)))
+                        },
+                        _ => panic!("no identifier in assign block"),
+                    };
+                    let mut children = vec![target, expr];
+                    children.extend(n.children.get(2).cloned());
+                    self.block(*cur).statements.push(Node::new(n.token.clone(), children));
+                },
+                Print | PrintNum => {
+                    let expr = self.hoist(&n.children[0], cur);
+                    self.block(*cur).statements.push(Node::new(n.token.clone(), vec![expr]));
+                },
+                Return => {
+                    if self.func.is_none() {
+                        unreachable!("rejected by check::check");
+                    }
+                    let expr = self.hoist(&n.children[0], cur);
+                    self.block(*cur).exit = Exit::Return(expr);
+                    // anything after a `return` is unreachable
+                    *cur = self.new_block(None);
+                },
+                Call(_) => {
+                    self.hoist_call(n, cur);
+                },
+                FuncSig if self.func.is_none() && self.scopes.len() == 1 => (),
+                FuncSig => unreachable!("rejected by check::check"),
+                _ => unreachable!("rejected by check::check"),
+            }
+        }
+    }
+
+    /// Lowers the statements of a `{}` body, which are in a scope of their own.
+    fn lower_block(&mut self, body: &Node, cur: &mut usize) {
+        self.scopes.push(HashMap::new());
+        self.lower_statements(&body.children, cur);
+        self.scopes.pop();
+    }
+
+    fn lower_loop(&mut self, cond: &Node, body: &Node, step: Option<&Node>, cur: &mut usize) {
+        let head = self.new_block(None);
+        self.block(*cur).exit = Exit::Jump(head);
+        *cur = head;
+
+        let cond = self.hoist(cond, cur);
+        let start = self.new_block(None);
+        let after = self.new_block(None);
+        self.block(*cur).exit = Exit::Branch(cond, start, after);
+
+        *cur = start;
+        self.lower_block(body, cur);
+        if let Some(step) = step {
+            self.lower_statements(std::slice::from_ref(step), cur);
+        }
+        self.block(*cur).exit = Exit::Jump(head);
+        *cur = after;
+    }
+
+    /// Replaces every call in `expr` with a temporary variable holding its result,
+    /// and renames variables to their unique names.
+    fn hoist(&mut self, expr: &Node, cur: &mut usize) -> Node {
+        let mut children = Vec::with_capacity(expr.children.len());
+        for n in &expr.children {
+            match &n.token {
+                Token::Call(_) => {
+                    let temp = self.hoist_call(n, cur);
+                    children.push(Node::leaf(Token::Ident(temp)));
+                },
+                Token::Ident(name) => children.push(Node::leaf(Token::Ident(self.resolve(name)))),
+                Token::Index => children.push(self.hoist_index(n, cur)),
+                Token::BinOp(op @ (BiOp::And | BiOp::Or)) if n.children.iter().any(contains_call) => {
+                    let lhs = children.split_off(last_value_start(&children));
+                    let temp = self.short_circuit(*op, lhs, &n.children[0], cur);
+                    children.push(Node::leaf(Token::Ident(temp)));
+                },
+                Token::BinOp(_) | Token::Len if !n.children.is_empty() => {
+                    let nested = n.children.iter().map(|child| self.hoist(child, cur)).collect();
+                    children.push(Node::new(n.token.clone(), nested));
+                },
+                _ => children.push(n.clone()),
+            }
+        }
+        Node::new(expr.token.clone(), children)
+    }
+
+    /// Lowers `lhs && rhs` or `lhs || rhs` to a branch, so the calls in `rhs` only run if
+    /// `lhs` doesn't decide the result. `lhs` is already hoisted. Returns the temporary
+    /// variable holding the result.
+    fn short_circuit(&mut self, op: BiOp, lhs: Vec<Node>, rhs: &Node, cur: &mut usize) -> String {
+        self.temps += 1;
+        let temp = format!("logic'{}", self.temps);
+        self.locals.get_mut(&self.func).unwrap().push((temp.clone(), Type::Bool));
+        self.assign_bool(&temp, lhs, *cur);
+
+        let rest = self.new_block(None);
+        let join = self.new_block(None);
+        let cond = Node::new(Token::Expr, vec![Node::leaf(Token::Ident(temp.clone()))]);
+        self.block(*cur).exit = match op {
+            BiOp::And => Exit::Branch(cond, rest, join),
+            _ => Exit::Branch(cond, join, rest),
+        };
+
+        *cur = rest;
+        let rhs = self.hoist(rhs, cur);
+        self.assign_bool(&temp, rhs.children, *cur);
+        self.block(*cur).exit = Exit::Jump(join);
+        *cur = join;
+        temp
+    }
+
+    /// Appends a statement to the block `id` that assigns the truth of the expression
+    /// `value` to the variable `name`.
+    fn assign_bool(&mut self, name: &str, mut value: Vec<Node>, id: usize) {
+        value.extend([Node::leaf(Token::Not), Node::leaf(Token::Not)]);
+        let target = Node::leaf(Token::Ident(name.to_string()));
+        let stmt = Node::new(Token::Assign, vec![target, Node::new(Token::Expr, value)]);
+        self.block(id).statements.push(stmt);
+    }
+
+    /// Hoists the calls out of the index of the array element `index` and renames the array.
+    fn hoist_index(&mut self, index: &Node, cur: &mut usize) -> Node {
+        let array = match &index.children[0].token {
+            Token::Ident(name) => Node::leaf(Token::Ident(self.resolve(name))),
+            _ => panic!("no identifier in array index"),
+        };
+        let expr = self.hoist(&index.children[1], cur);
+        Node::new(Token::Index, vec![array, expr])
+    }
+
+    /// Ends the current block with a call and continues in a new block. Returns the name
+    /// of the temporary variable holding the return value.
+    fn hoist_call(&mut self, call: &Node, cur: &mut usize) -> String {
+        let name = if let Token::Call(name) = &call.token { name.clone() } else { unreachable!() };
+        let func = match self.funcs.get(&name) {
+            Some(func) => func,
+            None => unreachable!("rejected by check::check"),
+        };
+        if func.children[1].children.len() != call.children.len() {
+            unreachable!("rejected by check::check");
+        }
+
+        let args = call.children.iter().map(|arg| self.hoist(arg, cur)).collect();
+        self.temps += 1;
+        let temp = format!("call'{}", self.temps);
+        self.locals.get_mut(&self.func).unwrap().push((temp.clone(), Type::U8));
+
+        let next = self.new_block(Some(temp.clone()));
+        self.block(*cur).exit = Exit::Call { func: name, args, next };
+        *cur = next;
+        temp
+    }
+
+    /// Returns the unique name of the variable `name` that is assigned a value of type `ty`.
+    /// It is declared if not already present, or if `declaration` is set and it was
+    /// declared in an outer scope.
+    fn local(&mut self, name: &str, ty: Type, declaration: bool) -> String {
+        let in_scope = self.scopes.last().is_some_and(|scope| scope.contains_key(name));
+        let unique = match self.scopes.iter().rev().find_map(|scope| scope.get(name)) {
+            Some(unique) if !declaration || in_scope => unique.clone(),
+            _ => return self.declare(name, ty),
+        };
+        let locals = &self.locals[&self.func];
+        match locals.iter().find(|(local, _)| local == &unique) {
+            Some((_, other)) if *other != ty && !other.can_hold(ty) => {
+                unreachable!("rejected by check::check")
+            },
+            _ => unique,
+        }
+    }
+
+    /// Declares `name` in the innermost scope, giving it a unique name within the function.
+    fn declare(&mut self, name: &str, ty: Type) -> String {
+        let locals = self.locals.get_mut(&self.func).unwrap();
+        let mut unique = name.to_string();
+        let mut i = 0;
+        while locals.iter().any(|(local, _)| local == &unique) {
+            i += 1;
+            unique = format!("{}'{}", name, i);
+        }
+        locals.push((unique.clone(), ty));
+        self.scopes.last_mut().unwrap().insert(name.to_string(), unique.clone());
+        unique
+    }
+
+    fn resolve(&self, name: &str) -> String {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .cloned()
+            .unwrap_or_else(|| name.to_string())
+    }
+
+    /// Creates an empty block in the current function and returns its id.
+    fn new_block(&mut self, resume: Option<String>) -> usize {
+        self.blocks.push(BasicBlock {
+            func: self.func.clone(),
+            resume: resume.map(Some),
+            statements: Vec::new(),
+            exit: Exit::Halt,
+        });
+        self.blocks.len()
+    }
+
+    fn block(&mut self, id: usize) -> &mut BasicBlock {
+        &mut self.blocks[id - 1]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::{parse, tokenize};
+
+    /// The number of cells the compiled `program` uses.
+    fn cells(program: &str) -> usize {
+        let root = parse(tokenize(program).unwrap()).unwrap();
+        let mut c = Parser::new(EofMode::Zero);
+        c.process_node(&root);
+        c.cells
+    }
+
+    #[test]
+    fn comparisons_free_their_cells() {
+        let program = |n: usize| {
+            let header = "var a = 1; var b = 2; var x: u16 = 300; var y: u32 = 5; var c = false;\n";
+            let body = "c = a >= b; c = a < b; c = a <= b; c = a > b; c = a == b; c = a != b;\n\
+                        c = x < y; c = x >= y; c = x == y;\n\
+                        for var i = 0; i < 3; i = i + 1 { c = i > a; }\n";
+            header.to_string() + &body.repeat(n)
+        };
+        assert_eq!(cells(&program(1)), cells(&program(100)));
+    }
+    #[test]
+    fn wide_arithmetic_frees_its_cells() {
+        let program = |n: usize| {
+            let header = "var x: u16 = 300; var y: u32 = 5; var s: i16 = -7; var t: i8 = 3;\n";
+            let body = "x = x + x * 3 - x / 7 % 5 ^ 2; y = y * y / 3 % 9 + y ^ 2; s = s / t % 2 - s * t;\n\
+                        printnum x; printnum y; printnum s; printnum -s; printnum !x;\n";
+            header.to_string() + &body.repeat(n)
+        };
+        assert_eq!(cells(&program(1)), cells(&program(20)));
+    }
+}
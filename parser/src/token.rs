@@ -1,19 +1,28 @@
 use std::collections::VecDeque;
 use std::fmt;
 
-use crate::parse::num;
 use regex::Regex;
 use Token::*;
 
+use crate::diagnostic::Diagnostic;
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Token {
     // Std functions
     Print,
+    PrintNum,
     GetLine,
     GetChar,
+    ReadNum,
+    Len,
     // Signatures
     FuncSig,
     VarSig,
+    ConstSig,
+    // Functions
+    Params,
+    Call(String),
+    Return,
     // Conditionals
     Branch,
     If,
@@ -22,14 +31,21 @@ pub enum Token {
     While,
     For,
     // Literals
-    NumLit(num),
+    NumLit(u32),
+    BoolLit(bool),
     StrLit(String),
     // Identifiers
     Ident(String),
+    TypeName(String),
+    // Array elements
+    Index,
     // Expressions
     Expr,
+    // Statement lists
+    Block,
     // Unary operators
     Not,
+    Neg,
     // Binary operators
     Assign,
     BinOp(BiOp),
@@ -38,14 +54,60 @@ pub enum Token {
     RBracket,
     LParent,
     RParent,
+    LSquare,
+    RSquare,
     Semicolon,
+    Colon,
+    Comma,
     Comment(String),
     Whitespace,
-    EOF,
-    Err(String),
+    Eof,
     Root,
 }
 
+impl fmt::Display for Token {
+    /// Describes the token the way it is written in the source code, for error messages.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            NumLit(val) => return write!(f, "`{}`", val),
+            BoolLit(val) => return write!(f, "`{}`", val),
+            Ident(name) | TypeName(name) | Call(name) => return write!(f, "`{}`", name),
+            BinOp(op) => return write!(f, "`{}`", op),
+            Print => "`print`",
+            PrintNum => "`printnum`",
+            GetLine => "`input()`",
+            GetChar => "`getchar()`",
+            ReadNum => "`readnum()`",
+            Len => "`len`",
+            FuncSig => "`fun`",
+            VarSig => "`var`",
+            ConstSig => "`const`",
+            Return => "`return`",
+            If => "`if`",
+            Else => "`else`",
+            While => "`while`",
+            For => "`for`",
+            StrLit(_) => "a string",
+            Not => "`!`",
+            Neg => "`-`",
+            Assign => "`=`",
+            LBracket => "`{`",
+            RBracket => "`}`",
+            LParent => "`(`",
+            RParent => "`)`",
+            LSquare => "`[`",
+            RSquare => "`]`",
+            Semicolon => "`;`",
+            Colon => "`:`",
+            Comma => "`,`",
+            Comment(_) => "a comment",
+            Eof => "the end of the program",
+            Params | Branch | Index | Expr | Block | Whitespace | Root => return write!(f, "{:?}", self),
+        };
+        write!(f, "{}", text)
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum BiOp {
     // Arithmetic
@@ -53,6 +115,7 @@ pub enum BiOp {
     Sub,
     Mul,
     Div,
+    Mod,
     Pow,
     // Comparators
     Equal,
@@ -61,9 +124,36 @@ pub enum BiOp {
     GreaterOrEqual,
     Less,
     Greater,
+    // Logical
+    And,
+    Or,
     Invalid,
 }
 
+impl fmt::Display for BiOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use BiOp::*;
+        let symbol = match self {
+            Add => "+",
+            Sub => "-",
+            Mul => "*",
+            Div => "/",
+            Mod => "%",
+            Pow => "^",
+            Equal => "==",
+            NotEqual => "!=",
+            LessOrEqual => "<=",
+            GreaterOrEqual => ">=",
+            Less => "<",
+            Greater => ">",
+            And => "&&",
+            Or => "||",
+            Invalid => "?",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
 impl BiOp {
     fn from(s: &str) -> Self {
         use BiOp::*;
@@ -72,6 +162,7 @@ impl BiOp {
             "-" => Sub,
             "*" => Mul,
             "/" => Div,
+            "%" => Mod,
             "^" => Pow,
             "==" => Equal,
             "!=" => NotEqual,
@@ -79,6 +170,8 @@ impl BiOp {
             ">=" => GreaterOrEqual,
             "<" => Less,
             ">" => Greater,
+            "&&" => And,
+            "||" => Or,
             _ => Invalid,
         }
     }
@@ -86,21 +179,25 @@ impl BiOp {
     fn prio(op: BiOp) -> u8 {
         use BiOp::*;
         match op {
-            Pow => 4,
-            Mul => 3,
-            Div => 3,
-            Add => 2,
-            Sub => 2,
-            Equal => 1,
-            NotEqual => 1,
-            LessOrEqual => 1,
-            GreaterOrEqual => 1,
-            Less => 1,
-            Greater => 1,
+            Pow => 7,
+            Mul => 5,
+            Div => 5,
+            Mod => 5,
+            Add => 4,
+            Sub => 4,
+            Equal => 3,
+            NotEqual => 3,
+            LessOrEqual => 3,
+            GreaterOrEqual => 3,
+            Less => 3,
+            Greater => 3,
+            And => 2,
+            Or => 1,
             Invalid => panic!("invalid operator"),
         }
     }
 
+    /// Checks if the operator is left associative, e.g. `a - b - c` is `(a - b) - c`.
     fn is_assoc(op: BiOp) -> bool {
         use BiOp::*;
         match op {
@@ -112,67 +209,169 @@ impl BiOp {
             GreaterOrEqual => true,
             Less => true,
             Greater => true,
-            Sub => false,
-            Div => false,
+            Sub => true,
+            Div => true,
+            Mod => true,
+            And => true,
+            Or => true,
             Pow => false,
             Invalid => panic!("invalid operator"),
         }
     }
 }
 
-pub fn tokenize(program: String) -> VecDeque<Token> {
-    let patterns: [(Token, &'static str); 22] = [
+/// A part of the source code, given by the byte offsets it starts and ends at and
+/// the line and column it starts at, which are counted from 1. Nodes that were not
+/// parsed from the source have the default span, whose line is 0.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end:   usize,
+    pub line:  usize,
+    pub col:   usize,
+}
+
+impl Span {
+    /// The smallest span that contains both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        let first = if self.start <= other.start { self } else { other };
+        Span { end: self.end.max(other.end), ..first }
+    }
+
+    /// The smallest span that contains all of `spans`, if there are any.
+    fn covering(spans: impl IntoIterator<Item = Span>) -> Option<Span> {
+        spans.into_iter().reduce(Span::to)
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+/// The tokens of a program, each with its span.
+pub struct Tokens {
+    tokens: VecDeque<(Token, Span)>,
+    /// The span of the last token that was popped.
+    last:   Span,
+    /// The syntax errors found so far, in statements that were skipped.
+    errors: Vec<Diagnostic>,
+}
+
+impl Tokens {
+    fn front(&self) -> Option<&Token> {
+        self.get(0)
+    }
+
+    fn get(&self, i: usize) -> Option<&Token> {
+        self.tokens.get(i).map(|(token, _)| token)
+    }
+
+    fn pop_front(&mut self) -> Option<Token> {
+        let (token, span) = self.tokens.pop_front()?;
+        self.last = span;
+        Some(token)
+    }
+
+    /// The span of the next token, or of the last one if there are none left.
+    fn span(&self) -> Span {
+        self.tokens.front().map_or(self.last, |&(_, span)| span)
+    }
+
+    /// The span from `start` to the end of the last token that was popped.
+    fn since(&self, start: Span) -> Span {
+        start.to(self.last)
+    }
+}
+
+impl fmt::Debug for Tokens {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.tokens.iter().map(|(token, _)| token)).finish()
+    }
+}
+
+/// Splits `program` into tokens. Returns an error for every part of it that isn't a token.
+pub fn tokenize(program: &str) -> Result<Tokens, Vec<Diagnostic>> {
+    let patterns: [(Token, &'static str); 31] = [
         // Std functions
         (Print,                     r"print\s"),
+        (PrintNum,                  r"printnum\s"),
         (GetLine,                   r"input\(\)"),
         (GetChar,                   r"getchar\(\)"),
+        (ReadNum,                   r"readnum\(\)"),
         // Keywords
         (FuncSig,                   r"fun\s"),
         (VarSig,                    r"var\s"),
+        (ConstSig,                  r"const\s"),
+        (Return,                    r"return\b"),
         (If,                        r"if\s"),
         (Else,                      r"else\s"),
         (While,                     r"while\s"),
         (For,                       r"for\s"),
         // Literals
         (NumLit(0),                 r"\d+"),
+        (BoolLit(false),            r"(true|false)\b"),
         (StrLit("".to_string()),    r#"("(\\.|[^"])*"|'(\\.|[^'])*')"#),
         // Identifiers
         (Ident("".to_string()),     r"[\pL][\pL\d]*"),
-        // Unary operators
-        (Not,                       r"!"),
         // Binary operators
-        (BinOp(BiOp::Invalid),      r"(==|!=|<=|>=|<|>|\^|\+|-|\*|/)"),
+        (BinOp(BiOp::Invalid),      r"(==|!=|<=|>=|&&|\|\||<|>|\^|\+|-|\*|/|%)"),
         (Assign,                    r"="),
+        // Unary operators (after binary operators, so `!=` is not read as `!`)
+        (Not,                       r"!"),
         // Syntax tokens
         (LBracket,                  r"\{"),
         (RBracket,                  r"\}"),
         (LParent,                   r"\("),
         (RParent,                   r"\)"),
+        (LSquare,                   r"\["),
+        (RSquare,                   r"\]"),
         (Semicolon,                 r";"),
+        (Colon,                     r":"),
+        (Comma,                     r","),
         (Comment("".to_string()),   r"#.*"),
         (Whitespace,                r"\s+"),
     ];
 
     let mut tokens = VecDeque::new();
+    let mut spans = Vec::new();
+    let mut errors = Vec::new();
     let patterns: Vec<(Token, Regex)> = patterns
         .iter()
         .map(|s| (s.0.clone(), Regex::new(&(r"^".to_owned() + s.1)).unwrap()))
         .collect();
     let mut ptr = 0;
+    let (mut line, mut col) = (1, 1);
+    // where the last token ends, which is where the program is reported to end
+    let mut end = Span { start: 0, end: 0, line, col };
     // While not at EOF, find next token
     'main: while ptr < program.len() {
         // Get a &str to code after current position
         let buf = program.split_at(ptr).1;
         for (kind, re) in &patterns {
             if let Some(m) = re.find(buf) {
+                let span = Span { start: ptr, end: ptr + m.end(), line, col };
                 // Match found! Move pointer forward for next token.
                 ptr += m.end();
+                for c in m.as_str().chars() {
+                    if c == '\n' {
+                        line += 1;
+                        col = 1;
+                    } else {
+                        col += 1;
+                    }
+                }
+                let len = tokens.len();
                 match *kind {
-                    NumLit(_) => tokens.push_back(NumLit(
-                        m.as_str()
-                            .parse()
-                            .expect("Error while parsing numeric literal."),
-                    )),
+                    NumLit(_) => match m.as_str().parse() {
+                        Ok(val) => tokens.push_back(NumLit(val)),
+                        Err(_) => errors.push(Diagnostic::error(
+                            span,
+                            format!("the number `{}` is too large, numbers can be at most {}", m.as_str(), u32::MAX),
+                        )),
+                    },
+                    BoolLit(_) => tokens.push_back(BoolLit(m.as_str() == "true")),
                     StrLit(_) => {
                         let mut strlit = m.as_str().chars();
                         strlit.next();
@@ -185,33 +384,71 @@ pub fn tokenize(program: String) -> VecDeque<Token> {
                     Whitespace => (),
                     _ => tokens.push_back(kind.clone()),
                 }
+                spans.extend((len..tokens.len()).map(|_| span));
+                if !matches!(kind, Whitespace | Comment(_)) {
+                    end = Span { start: ptr, end: ptr, line, col };
+                }
                 // Find next token.
                 continue 'main;
             }
         }
-        // If no match is found, add the first char in the buffer as an `Err`.
-        tokens.push_back(Err(buf.chars().next().unwrap_or(' ').to_string()));
-        ptr += 1;
+        // If no match is found, the buffer starts with a string that is never closed,
+        // or with chars that can't start a token. Either is reported, and the chars are
+        // skipped so the rest of the program is still checked for errors.
+        let start = ptr;
+        let span_col = col;
+        if buf.starts_with(['"', '\'']) {
+            // strings can span lines, so the rest of the program is part of the string
+            ptr = program.len();
+            errors.push(Diagnostic::error(Span { start, end: ptr, line, col: span_col }, "this string is never closed"));
+            break;
+        }
+        for c in buf.chars() {
+            if ptr > start && patterns.iter().any(|(_, re)| re.is_match(&program[ptr..])) {
+                break;
+            }
+            ptr += c.len_utf8();
+            col += 1;
+        }
+        let text = &program[start..ptr];
+        let message = if text.chars().count() == 1 {
+            format!("unexpected character `{}`", text)
+        } else {
+            format!("unexpected characters `{}`", text)
+        };
+        errors.push(Diagnostic::error(Span { start, end: ptr, line, col: span_col }, message));
+    }
+    if !errors.is_empty() {
+        return Err(errors);
     }
     // Reached end of program, so push an `EOF` token and quit.
-    tokens.push_back(EOF);
-    tokens
+    tokens.push_back(Eof);
+    spans.push(end);
+    Ok(Tokens { tokens: tokens.into_iter().zip(spans).collect(), last: Span::default(), errors: Vec::new() })
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Node {
     pub token: Token,
     pub children: Vec<Node>,
+    /// The part of the source code the node was parsed from.
+    pub span: Span,
 }
 
 impl Node {
-    fn new(token: Token, children: Vec<Node>) -> Self {
-        Node { token, children }
+    pub(crate) fn new(token: Token, children: Vec<Node>) -> Self {
+        Node { token, children, span: Span::default() }
     }
 
-    fn leaf(token: Token) -> Self {
+    pub(crate) fn leaf(token: Token) -> Self {
         Node::new(token, Vec::new())
     }
+
+    /// Sets the span of the node to `span`.
+    pub(crate) fn at(mut self, span: Span) -> Self {
+        self.span = span;
+        self
+    }
 }
 
 impl fmt::Display for Node {
@@ -242,138 +479,486 @@ impl fmt::Display for Node {
     }
 }
 
-pub fn parse(mut tokens: VecDeque<Token>) -> Node {
+/// Parses the tokens of a program into its syntax tree. Returns the syntax errors
+/// in the program if there are any.
+pub fn parse(mut tokens: Tokens) -> Result<Node, Vec<Diagnostic>> {
+    let start = tokens.span();
     let mut statements = Vec::new();
-    while let Some(node) = parse_next(&mut tokens) {
-        statements.push(node);
+    loop {
+        statements.extend(parse_statements(&mut tokens));
+        if tokens.front() != Some(&RBracket) {
+            break;
+        }
+        let error = Diagnostic::error(tokens.span(), "unexpected `}`, there is no `{` to close");
+        tokens.errors.push(error);
+        tokens.pop_front();
+    }
+    if !tokens.errors.is_empty() {
+        return Err(tokens.errors);
     }
-    Node::new(Root, statements)
+    Ok(Node::new(Root, statements).at(tokens.since(start)))
 }
 
-fn parse_next(tokens: &mut VecDeque<Token>) -> Option<Node> {
-    while let Some(token) = tokens.front() {
-        match token {
-            If => return Some(parse_branch(tokens)),
-            VarSig | Ident { .. } => return Some(parse_assign(tokens)),
-            Print => return Some(parse_print(tokens)),
-            Else => {
-                eprintln!("else-statement must come after an if-statement");
-                return None;
+/// Parses the statements up to the end of the program, or up to the `}` that ends
+/// the current body. A statement with a syntax error is skipped after adding the
+/// error to `tokens`, so the statements after it are still checked for errors.
+fn parse_statements(tokens: &mut Tokens) -> Vec<Node> {
+    let mut statements = Vec::new();
+    loop {
+        match parse_next(tokens) {
+            Ok(Some(node)) => statements.push(node),
+            Ok(None) => return statements,
+            Err(error) => {
+                tokens.errors.push(error);
+                synchronize(tokens);
             },
-            RBracket => {
+        }
+    }
+}
+
+/// Skips the rest of a statement with a syntax error, up to and including the next `;`.
+/// The `}` that ends the body the statement is in is left alone, and the bodies the
+/// statement opened are skipped whole, so their statements aren't taken for ones after it.
+fn synchronize(tokens: &mut Tokens) {
+    let mut depth = 0;
+    loop {
+        match tokens.front() {
+            None | Some(Eof) => return,
+            Some(RBracket) if depth == 0 => return,
+            Some(Semicolon) if depth == 0 => {
                 tokens.pop_front();
-                return None;
-            }
+                return;
+            },
+            Some(LBracket) => depth += 1,
+            Some(RBracket) => {
+                depth -= 1;
+                tokens.pop_front();
+                // an `else` after a skipped body is part of the same statement
+                if depth == 0 && tokens.front() != Some(&Else) {
+                    return;
+                }
+                continue;
+            },
+            _ => (),
+        }
+        tokens.pop_front();
+    }
+}
+
+/// Parses the next statement. Returns `None` at the end of the program, or at the
+/// `}` that ends the current body, which is left for the caller.
+fn parse_next(tokens: &mut Tokens) -> Result<Option<Node>, Diagnostic> {
+    while let Some(token) = tokens.front() {
+        let node = match token {
+            If => parse_branch(tokens)?,
+            While => parse_while(tokens)?,
+            For => parse_for(tokens)?,
+            Ident(name) if tokens.get(1) == Some(&LParent) => {
+                let name = name.clone();
+                let start = tokens.span();
+                tokens.pop_front();
+                let call = parse_call(name, start, tokens)?;
+                if call.token == Len {
+                    return Err(Diagnostic::error(call.span, "the length is computed but never used")
+                        .with_note("assign it to a variable, like `var n = len(s);`"));
+                }
+                expect(tokens, Semicolon, "`;` after the call")?;
+                call
+            },
+            VarSig | Ident { .. } => parse_assign(tokens, Semicolon)?,
+            ConstSig => parse_const(tokens)?,
+            Print | PrintNum => parse_print(tokens)?,
+            FuncSig => parse_function(tokens)?,
+            Return => parse_return(tokens)?,
+            Else => return error(tokens.span(), "`else` must come right after the body of an `if`"),
+            RBracket | Eof => return Ok(None),
             Comment(_) => {
                 tokens.pop_front();
+                continue;
             }
-            EOF => return None,
-            _ => unimplemented!("node start at {:?}", token),
-        }
+            _ => return Err(unexpected(tokens, "a statement")),
+        };
+        return Ok(Some(node));
+    }
+    Ok(None)
+}
+
+/// An error at `span` with the message `message`.
+fn error<T>(span: Span, message: impl Into<String>) -> Result<T, Diagnostic> {
+    Err(Diagnostic::error(span, message))
+}
+
+/// An error at the next token, saying that `expected` should be there instead.
+fn unexpected(tokens: &Tokens, expected: &str) -> Diagnostic {
+    let found = tokens.front().unwrap_or(&Eof);
+    Diagnostic::error(tokens.span(), format!("expected {}, found {}", expected, found))
+}
+
+/// Pops the next token if it is `token`, or else returns an error saying that
+/// `expected` should be there.
+fn expect(tokens: &mut Tokens, token: Token, expected: &str) -> Result<(), Diagnostic> {
+    if tokens.front() != Some(&token) {
+        return Err(unexpected(tokens, expected));
     }
-    None
+    tokens.pop_front();
+    Ok(())
 }
 
-fn parse_branch(tokens: &mut VecDeque<Token>) -> Node {
-    let mut children = Vec::with_capacity(3);
-    children.push(parse_numeric_expr(tokens));
-    
-    let mut body = Vec::new();
-    while let Some(n) = parse_next(tokens) {
-        body.push(n);
+/// Pops the next token if it is an identifier, and returns its name and span.
+fn expect_name(tokens: &mut Tokens, expected: &str) -> Result<(String, Span), Diagnostic> {
+    match tokens.front() {
+        Some(Ident(name)) => {
+            let name = name.clone();
+            tokens.pop_front();
+            Ok((name, tokens.last))
+        },
+        _ => Err(unexpected(tokens, expected)),
     }
-    children.push(Node::new(If, body));
+}
 
-    if let Some(Else) = tokens.front() {
-        children.push(parse_else(tokens));        
+fn parse_branch(tokens: &mut Tokens) -> Result<Node, Diagnostic> {
+    let start = tokens.span();
+    tokens.pop_front();
+    let cond = parse_expr(tokens, LBracket)?;
+    let body = parse_body(If, tokens)?;
+    let other = if tokens.front() == Some(&Else) {
+        parse_else(tokens)?
     } else {
-        children.push(Node::leaf(Else));
-    }
+        Node::leaf(Else).at(tokens.last)
+    };
 
-    Node::new(Branch, children)
+    Ok(Node::new(Branch, vec![cond, body, other]).at(tokens.since(start)))
 }
 
-fn parse_else(tokens: &mut VecDeque<Token>) -> Node {
+fn parse_else(tokens: &mut Tokens) -> Result<Node, Diagnostic> {
+    let start = tokens.span();
     tokens.pop_front();
-    let mut body = Vec::new();
-    match tokens.front() {
-        Some(If) => body.push(parse_branch(tokens)),
+    let body = match tokens.front() {
+        Some(If) => vec![parse_branch(tokens)?],
         Some(LBracket) => {
             tokens.pop_front();
-            while let Some(n) = parse_next(tokens) {
-                body.push(n);
-            }
+            parse_body(Else, tokens)?.children
         },
-        _ => panic!("expected else- or else if-statement"),
+        _ => return Err(unexpected(tokens, "`{` or `if` after `else`")),
+    };
+
+    Ok(Node::new(Else, body).at(tokens.since(start)))
+}
+
+/// Parses the statements up to the `}` that ends a body into a `kind` node. The `{`
+/// that starts the body must be the last token that was popped.
+fn parse_body(kind: Token, tokens: &mut Tokens) -> Result<Node, Diagnostic> {
+    let start = tokens.last;
+    let body = parse_statements(tokens);
+    if tokens.front() != Some(&RBracket) {
+        return error(start, "this `{` is never closed");
     }
+    tokens.pop_front();
+    Ok(Node::new(kind, body).at(tokens.since(start)))
+}
 
-    Node::new(Else, body)
+fn parse_while(tokens: &mut Tokens) -> Result<Node, Diagnostic> {
+    let start = tokens.span();
+    tokens.pop_front();
+    let cond = parse_expr(tokens, LBracket)?;
+    let body = parse_body(Block, tokens)?;
+
+    Ok(Node::new(While, vec![cond, body]).at(tokens.since(start)))
 }
 
-fn parse_assign(tokens: &mut VecDeque<Token>) -> Node {
-    let variable = if Some(&VarSig) == tokens.front() {
+fn parse_for(tokens: &mut Tokens) -> Result<Node, Diagnostic> {
+    let start = tokens.span();
+    tokens.pop_front();
+    let init = parse_assign(tokens, Semicolon)?;
+    let cond = parse_expr(tokens, Semicolon)?;
+    let step = parse_assign(tokens, LBracket)?;
+    let body = parse_body(Block, tokens)?;
+
+    Ok(Node::new(For, vec![init, cond, step, body]).at(tokens.since(start)))
+}
+
+/// Parses `var x = expr;` into a `VarSig` node and `x = expr;` into an
+/// `Assign` node, both with the children `[Ident, Expr]`. An array element
+/// `x[expr]` is an `Index` node with the children `[Ident, Expr]` instead of
+/// the `Ident`. Arrays are declared as `var x[size];`, which is a `VarSig`
+/// node with only the `Index` child. A declaration can give the type of the
+/// variable, as in `var x: u16 = expr;`, which adds a `TypeName` child.
+/// The value is ended by `end`, which is `;` except in the head of a `for` loop.
+fn parse_assign(tokens: &mut Tokens, end: Token) -> Result<Node, Diagnostic> {
+    let start = tokens.span();
+    let kind = if Some(&VarSig) == tokens.front() {
         tokens.pop_front();
-        Node::leaf(tokens.pop_front().unwrap())
+        VarSig
     } else {
-        Node::leaf(tokens.pop_front().unwrap())
+        Assign
     };
-    if tokens.pop_front() != Some(Assign) {
-        panic!("expected assign operator");
+    let (name, span) = expect_name(tokens, "a variable name")?;
+    let mut variable = Node::leaf(Ident(name)).at(span);
+    if tokens.front() == Some(&LSquare) {
+        variable = parse_index(variable, tokens)?;
+        if kind == VarSig && tokens.front() == Some(&Semicolon) {
+            tokens.pop_front();
+            return Ok(Node::new(kind, vec![variable]).at(tokens.since(start)));
+        }
+    }
+    let mut ty = None;
+    if kind == VarSig && tokens.front() == Some(&Colon) {
+        tokens.pop_front();
+        let (name, span) = expect_name(tokens, "a type name after `:`")?;
+        ty = Some(Node::leaf(TypeName(name)).at(span));
+    }
+    expect(tokens, Assign, "`=`")?;
+    let expr = parse_expr(tokens, end)?;
+    let mut children = vec![variable, expr];
+    children.extend(ty);
+    Ok(Node::new(kind, children).at(tokens.since(start)))
+}
+
+/// Parses `const N = expr;` into a `ConstSig` node with the children `[Ident, Expr]`.
+fn parse_const(tokens: &mut Tokens) -> Result<Node, Diagnostic> {
+    let start = tokens.span();
+    tokens.pop_front();
+    let (name, span) = expect_name(tokens, "a constant name after `const`")?;
+    expect(tokens, Assign, "`=`")?;
+    let expr = parse_expr(tokens, Semicolon)?;
+    Ok(Node::new(ConstSig, vec![Node::leaf(Ident(name)).at(span), expr]).at(tokens.since(start)))
+}
+
+/// Parses `fun name(a, b) { ... }` into a `FuncSig` node with the children
+/// `[Ident(name), Params, Block]`.
+fn parse_function(tokens: &mut Tokens) -> Result<Node, Diagnostic> {
+    let start = tokens.span();
+    tokens.pop_front();
+    let (name, span) = expect_name(tokens, "a function name after `fun`")?;
+    let params_start = tokens.span();
+    expect(tokens, LParent, "`(` after the function name")?;
+
+    let mut params = Vec::new();
+    while tokens.front() != Some(&RParent) {
+        let (param, span) = expect_name(tokens, "a parameter name or `)`")?;
+        params.push(Node::leaf(Ident(param)).at(span));
+        if tokens.front() != Some(&RParent) {
+            expect(tokens, Comma, "`,` or `)` after the parameter")?;
+        }
+    }
+    tokens.pop_front();
+    let params = Node::new(Params, params).at(tokens.since(params_start));
+    expect(tokens, LBracket, "`{` before the function body")?;
+    let body = parse_body(Block, tokens)?;
+
+    Ok(Node::new(FuncSig, vec![Node::leaf(Ident(name)).at(span), params, body]).at(tokens.since(start)))
+}
+
+fn parse_return(tokens: &mut Tokens) -> Result<Node, Diagnostic> {
+    let start = tokens.span();
+    tokens.pop_front();
+    // a bare `return;` returns 0, like falling off the end of the function
+    if tokens.front() == Some(&Semicolon) {
+        tokens.pop_front();
+        let zero = Node::leaf(NumLit(0)).at(start);
+        let expr = Node::new(Expr, vec![zero]).at(start);
+        return Ok(Node::new(Return, vec![expr]).at(tokens.since(start)));
+    }
+    let expr = parse_expr(tokens, Semicolon)?;
+    Ok(Node::new(Return, vec![expr]).at(tokens.since(start)))
+}
+
+/// Parses the argument list of a call to `name`, starting at its `(`. The name
+/// starts at `start`. Every argument becomes an `Expr` child of the resulting
+/// `Call` node, or `Len` node for the builtin `len`.
+fn parse_call(name: String, start: Span, tokens: &mut Tokens) -> Result<Node, Diagnostic> {
+    tokens.pop_front();
+    let mut args = Vec::new();
+    if tokens.front() == Some(&RParent) {
+        tokens.pop_front();
+    } else {
+        loop {
+            args.push(parse_expr_until_end(tokens)?);
+            match tokens.front() {
+                Some(Comma) => {
+                    tokens.pop_front();
+                },
+                Some(RParent) => {
+                    tokens.pop_front();
+                    break;
+                },
+                _ => return Err(unexpected(tokens, "`,` or `)` after the argument")),
+            }
+        }
     }
-    let expr = parse_numeric_expr(tokens);
-    Node::new(Assign, vec![variable, expr])
+    let token = if name == "len" { Len } else { Call(name) };
+    Ok(Node::new(token, args).at(tokens.since(start)))
 }
 
-fn parse_numeric_expr(tokens: &mut VecDeque<Token>) -> Node {
+/// Parses the `[expr]` after the array `array` into an `Index` node.
+fn parse_index(array: Node, tokens: &mut Tokens) -> Result<Node, Diagnostic> {
+    tokens.pop_front();
+    let index = parse_expr_until_end(tokens)?;
+    expect(tokens, RSquare, "`]` after the array index")?;
+    let span = tokens.since(array.span);
+    Ok(Node::new(Index, vec![array, index]).at(span))
+}
+
+/// Parses an expression that must be ended by `end`, which is consumed.
+fn parse_expr(tokens: &mut Tokens, end: Token) -> Result<Node, Diagnostic> {
+    let expr = parse_expr_until_end(tokens)?;
+    expect(tokens, end.clone(), &format!("{} after the expression", end))?;
+    Ok(expr)
+}
+
+/// Parses an expression into reverse polish notation, up to the token that ends
+/// it, which is left for the caller. An unmatched `)`, a `,` or a `]` ends it as
+/// well, so arguments of calls and array indices can be parsed.
+fn parse_expr_until_end(tokens: &mut Tokens) -> Result<Node, Diagnostic> {
     let mut rpn_expr = Vec::new();
     let mut ops = Vec::new();
-    while let Some(token) = tokens.pop_front() {
+    let mut depth = 0;
+    // set where an operand is expected, so a `-` there is a negation
+    let mut expect_operand = true;
+    while let Some(token) = tokens.front() {
+        let end = matches!(token, Semicolon | LBracket | RBracket | Comma | RSquare | Eof);
+        if end || (token == &RParent && depth == 0) {
+            break;
+        }
+        let span = tokens.span();
+        let token = tokens.pop_front().unwrap();
+        let operand = matches!(
+            token,
+            NumLit(_) | BoolLit(_) | StrLit(_) | Ident(_) | GetChar | GetLine | ReadNum | RParent
+        );
         match token {
-            NumLit(_) => rpn_expr.push(Node::leaf(token)),
-            Ident { .. } => rpn_expr.push(Node::leaf(token)),
+            NumLit(_) | BoolLit(_) | StrLit(_) | GetChar | GetLine | ReadNum => {
+                rpn_expr.push(Node::leaf(token).at(span));
+            },
+            Ident(name) if tokens.front() == Some(&LParent) => {
+                rpn_expr.push(parse_call(name, span, tokens)?);
+            },
+            Ident(_) if tokens.front() == Some(&LSquare) => {
+                rpn_expr.push(parse_index(Node::leaf(token).at(span), tokens)?);
+            },
+            Ident { .. } => rpn_expr.push(Node::leaf(token).at(span)),
+            // prefix operators apply to the operand after them, so nothing is popped
+            Not => ops.push((token, span)),
+            BinOp(BiOp::Sub) if expect_operand => ops.push((Neg, span)),
             BinOp(o) => {
-                while let Some(BinOp(other_op)) = ops.last() {
-                    if (BiOp::is_assoc(o) && BiOp::prio(o) <= BiOp::prio(*other_op))
-                        || (!BiOp::is_assoc(o) && BiOp::prio(o) < BiOp::prio(*other_op))
+                while let Some(other_prio) = ops.last().and_then(|(op, _)| prio(op)) {
+                    if (BiOp::is_assoc(o) && BiOp::prio(o) <= other_prio)
+                        || (!BiOp::is_assoc(o) && BiOp::prio(o) < other_prio)
                     {
-                        let op = ops.pop().unwrap();
-                        rpn_expr.push(Node::leaf(op));
+                        let (op, span) = ops.pop().unwrap();
+                        rpn_expr.push(Node::leaf(op).at(span));
                     } else {
                         break;
                     }
                 }
-                ops.push(token);
+                ops.push((token, span));
             }
-            LParent => ops.push(token),
+            LParent => {
+                depth += 1;
+                ops.push((token, span));
+            },
             RParent => {
-                while let Some(op) = ops.pop() {
+                depth -= 1;
+                while let Some((op, span)) = ops.pop() {
                     if op == LParent {
                         break;
                     } else {
-                        rpn_expr.push(Node::leaf(op));
+                        rpn_expr.push(Node::leaf(op).at(span));
                     }
                 }
             }
-            Semicolon | LBracket | RBracket => break,
-            _ => (),
+            Comment(_) => continue,
+            _ => return error(span, format!("unexpected {} in expression", token)),
+        }
+        expect_operand = !operand;
+    }
+
+    while let Some((op, span)) = ops.pop() {
+        if op == LParent {
+            return error(span, "this `(` is never closed");
         }
+        rpn_expr.push(Node::leaf(op).at(span));
     }
+    if rpn_expr.is_empty() {
+        return Err(unexpected(tokens, "an expression"));
+    }
+    let rpn_expr = nest_logical(rpn_expr)?;
+    let span = Span::covering(rpn_expr.iter().map(|n| n.span)).unwrap_or(tokens.last);
+    Ok(Node::new(Expr, rpn_expr).at(span))
+}
+
+/// The precedence of an operator on the operator stack, if it is one.
+fn prio(token: &Token) -> Option<u8> {
+    match token {
+        BinOp(op) => Some(BiOp::prio(*op)),
+        // negation binds weaker than `^`, so `-2^2` is `-(2^2)`
+        Neg => Some(6),
+        Not => Some(8),
+        _ => None,
+    }
+}
 
-    while let Some(op) = ops.pop() {
-        rpn_expr.push(Node::leaf(op));
+/// Moves the right operand of every `&&` and `||` into the operator node as an `Expr`
+/// child, so it can be skipped at runtime when the left operand decides the result.
+/// Returns an error if an operator is missing an operand, or if there are values
+/// without an operator between them.
+fn nest_logical(rpn_expr: Vec<Node>) -> Result<Vec<Node>, Diagnostic> {
+    // every entry is the part of the expression that computes one value
+    let mut stack: Vec<Vec<Node>> = Vec::new();
+    for n in rpn_expr {
+        match n.token {
+            BinOp(BiOp::And) | BinOp(BiOp::Or) if stack.len() >= 2 => {
+                let rhs = stack.pop().unwrap();
+                let mut lhs = stack.pop().unwrap();
+                let span = Span::covering(rhs.iter().map(|n| n.span)).unwrap_or(n.span);
+                lhs.push(Node::new(n.token, vec![Node::new(Expr, rhs).at(span)]).at(n.span));
+                stack.push(lhs);
+            },
+            BinOp(_) if stack.len() >= 2 => {
+                let rhs = stack.pop().unwrap();
+                let mut lhs = stack.pop().unwrap();
+                lhs.extend(rhs);
+                lhs.push(n);
+                stack.push(lhs);
+            },
+            Not | Neg if !stack.is_empty() => stack.last_mut().unwrap().push(n),
+            BinOp(_) | Not | Neg => return error(n.span, format!("{} is missing an operand", n.token)),
+            _ => stack.push(vec![n]),
+        }
     }
-    Node::new(Expr, rpn_expr)
+    if let Some(value) = stack.get(1) {
+        let span = Span::covering(value.iter().map(|n| n.span)).unwrap_or_default();
+        return error(span, "expected an operator before this value");
+    }
+    Ok(stack.into_iter().flatten().collect())
 }
 
-fn parse_print(tokens: &mut VecDeque<Token>) -> Node {
+fn parse_print(tokens: &mut Tokens) -> Result<Node, Diagnostic> {
+    let start = tokens.span();
     let token = tokens.pop_front().unwrap();
-    if let Some(StrLit(_)) = tokens.front() {
-        // TODO: Substitute with `parse_string_expr`
-        let strlit = Node::leaf(tokens.pop_front().unwrap());
-        tokens.pop_front();
-        Node::new(token, vec![strlit])
-    } else {
-        let expr = parse_numeric_expr(tokens);
-        Node::new(token, vec![expr])
+    let expr = parse_expr(tokens, Semicolon)?;
+    Ok(Node::new(token, vec![expr]).at(tokens.since(start)))
+}
+
+/// Replaces the escape sequences in a string literal with the characters they stand for.
+pub(crate) fn unescape(s: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        let c = if c == '\\' {
+            match chars.next() {
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some('t') => '\t',
+                Some('"') => '\"',
+                Some('\'') => '\'',
+                Some('\\') => '\\',
+                Some(esc) => esc,
+                // the tokenizer rejects literals ending in a lone `\`
+                None => c,
+            }
+        } else { c };
+        out.push(c as u8);
     }
-}
\ No newline at end of file
+    out
+}
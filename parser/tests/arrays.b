@@ -0,0 +1,19 @@
+# Arrays have a fixed length, and their elements are accessed with constant indices
+
+var a[5];
+a[0] = 1;
+a[1] = a[0] + 1;
+a[2] = a[1] * 2;
+a[3] = a[2] + a[1] * 2;
+a[2 + 2] = a[3] * 2;
+printnum a[0] + a[1] + a[2] + a[3] + a[4];
+print "\n";
+
+var i = 0;
+var sum[1];
+while i < 3 {
+    sum[0] = sum[0] + a[4];
+    i = i + 1;
+}
+printnum sum[0];
+print "\n";
@@ -0,0 +1,21 @@
+# Comparisons give bools, which can also be declared and used as 0 or 1 in arithmetic
+
+var done: bool = false;
+var count = 0;
+while !done {
+    count = count + 1;
+    done = count >= 5;
+}
+printnum count;
+print " ";
+printnum done;
+print "\n";
+
+var small = count < 3;
+var both = done && !small;
+printnum both;
+print " ";
+printnum small || true;
+print " ";
+printnum (count > 1) + (count > 2) + (count > 9);
+print "\n";
@@ -0,0 +1,25 @@
+# Constants are replaced by their values at compile time, so they take no cells
+
+const WIDTH = 10 * 4;
+const HALF = WIDTH / 2;
+const BIG = 1000 * 1000;
+const VERBOSE = true;
+
+fun clamp(x) {
+    if x > HALF {
+        return HALF;
+    }
+    return x;
+}
+
+var row[WIDTH];
+row[HALF] = 7;
+printnum row[20] + WIDTH;
+print " ";
+printnum clamp(100);
+print " ";
+var n: u32 = BIG + 1;
+printnum n;
+if VERBOSE {
+    print "\n";
+}
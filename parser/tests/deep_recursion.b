@@ -0,0 +1,12 @@
+# Calls can be nested hundreds of levels deep. Nesting them deeper than the
+# call stack allows prints an error and halts the program.
+
+fun depth(n) {
+    if n == 0 {
+        return 0;
+    }
+    return depth(n - 1) + 1;
+}
+
+printnum depth(250);
+print "\n";
@@ -0,0 +1,12 @@
+var a = 200;
+var b = 7;
+
+print a / b / 10 + 48;
+print a / b % 10 + 48;
+print a % b + 48;
+print "\n";
+
+# division by zero gives 0, and the remainder is the dividend
+print 5 / 0 + 48;
+print 5 % 0 + 48;
+print "\n";
@@ -0,0 +1,18 @@
+var i = 7;
+
+for var i = 0; i < 5; i = i + 1 {
+    for var j = 0; j <= i; j = j + 1 {
+        print "*";
+    }
+    print "\n";
+}
+
+# `i` refers to the outer variable again
+print i + 48;
+print "\n";
+
+var n = 0;
+for n = 3; n; n = n - 1 {
+    print n + 48;
+}
+print "\n";
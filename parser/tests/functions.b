@@ -0,0 +1,31 @@
+fun max(a, b) {
+    if a > b {
+        return a;
+    }
+    return b;
+}
+
+fun square(x) {
+    return x * x;
+}
+
+fun countdown(n) {
+    while n {
+        print n + 48;
+        if n == 3 {
+            return;
+        }
+        n = n - 1;
+    }
+    print "unreachable";
+}
+
+fun newline() {
+    print "\n";
+}
+
+print max(3, 7) + 48;
+print max(square(2), 2 + 1) + 48;
+newline();
+countdown(6);
+newline();
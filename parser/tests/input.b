@@ -0,0 +1,13 @@
+print "name: ";
+var name = input();
+print "hello ";
+print name;
+print "!\n";
+
+# reads single characters, end of input reads as 0
+var c = getchar();
+while c {
+    print c + 1;
+    c = getchar();
+}
+print "\n";
@@ -0,0 +1,27 @@
+fun shout(x) {
+    print "!";
+    return x;
+}
+
+var a = 5;
+var b = 12;
+
+if a > 0 && b < 10 {
+    print "both\n";
+} else if a > 0 || b < 10 {
+    print "either\n";
+}
+
+if !(a == 5) {
+    print "wrong\n";
+} else {
+    print "a is 5\n";
+}
+
+# the right side is only evaluated when needed
+print (0 && shout(1)) + 48;
+print (1 || shout(0)) + 48;
+print (1 && shout(2)) + 48;
+print (0 || shout(0)) + 48;
+print !a + !!b + (a != b) + 48;
+print "\n";
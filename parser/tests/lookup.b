@@ -0,0 +1,32 @@
+# Arrays can be indexed by values only known at runtime
+
+var squares[10];
+for var i = 0; i < 10; i = i + 1 {
+    squares[i] = i * i;
+}
+for var i = 0; i < 10; i = i + 1 {
+    printnum squares[9 - i];
+    print " ";
+}
+print "\n";
+
+# reverses the digits read from input
+var digits[8];
+var n = 0;
+var c = getchar();
+while c >= 48 && c <= 57 && n < 8 {
+    digits[n] = c;
+    n = n + 1;
+    c = getchar();
+}
+while n {
+    n = n - 1;
+    print digits[n];
+}
+print "\n";
+
+# indices out of bounds read as 0 and writes to them are ignored
+var small[2];
+small[n + 5] = 1;
+printnum small[n + 5] + small[0] + small[1];
+print "\n";
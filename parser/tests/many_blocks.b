@@ -0,0 +1,25 @@
+# A recursive function makes the whole program run as numbered blocks, and every
+# `if` adds a few of them, so this program needs more than fit in a single cell.
+
+fun fact(n) {
+    if n == 0 {
+        return 1;
+    }
+    return n * fact(n - 1);
+}
+
+var x = 0;
+if x == 0 { x = x + 1; } if x == 1 { x = x + 1; } if x == 2 { x = x + 1; } if x == 3 { x = x + 1; } if x == 4 { x = x + 1; } if x == 5 { x = x + 1; } if x == 6 { x = x + 1; } if x == 7 { x = x + 1; } if x == 8 { x = x + 1; } if x == 9 { x = x + 1; }
+if x == 10 { x = x + 1; } if x == 11 { x = x + 1; } if x == 12 { x = x + 1; } if x == 13 { x = x + 1; } if x == 14 { x = x + 1; } if x == 15 { x = x + 1; } if x == 16 { x = x + 1; } if x == 17 { x = x + 1; } if x == 18 { x = x + 1; } if x == 19 { x = x + 1; }
+if x == 20 { x = x + 1; } if x == 21 { x = x + 1; } if x == 22 { x = x + 1; } if x == 23 { x = x + 1; } if x == 24 { x = x + 1; } if x == 25 { x = x + 1; } if x == 26 { x = x + 1; } if x == 27 { x = x + 1; } if x == 28 { x = x + 1; } if x == 29 { x = x + 1; }
+if x == 30 { x = x + 1; } if x == 31 { x = x + 1; } if x == 32 { x = x + 1; } if x == 33 { x = x + 1; } if x == 34 { x = x + 1; } if x == 35 { x = x + 1; } if x == 36 { x = x + 1; } if x == 37 { x = x + 1; } if x == 38 { x = x + 1; } if x == 39 { x = x + 1; }
+if x == 40 { x = x + 1; } if x == 41 { x = x + 1; } if x == 42 { x = x + 1; } if x == 43 { x = x + 1; } if x == 44 { x = x + 1; } if x == 45 { x = x + 1; } if x == 46 { x = x + 1; } if x == 47 { x = x + 1; } if x == 48 { x = x + 1; } if x == 49 { x = x + 1; }
+if x == 50 { x = x + 1; } if x == 51 { x = x + 1; } if x == 52 { x = x + 1; } if x == 53 { x = x + 1; } if x == 54 { x = x + 1; } if x == 55 { x = x + 1; } if x == 56 { x = x + 1; } if x == 57 { x = x + 1; } if x == 58 { x = x + 1; } if x == 59 { x = x + 1; }
+if x == 60 { x = x + 1; } if x == 61 { x = x + 1; } if x == 62 { x = x + 1; } if x == 63 { x = x + 1; } if x == 64 { x = x + 1; } if x == 65 { x = x + 1; } if x == 66 { x = x + 1; } if x == 67 { x = x + 1; } if x == 68 { x = x + 1; } if x == 69 { x = x + 1; }
+if x == 70 { x = x + 1; } if x == 71 { x = x + 1; } if x == 72 { x = x + 1; } if x == 73 { x = x + 1; } if x == 74 { x = x + 1; } if x == 75 { x = x + 1; } if x == 76 { x = x + 1; } if x == 77 { x = x + 1; } if x == 78 { x = x + 1; } if x == 79 { x = x + 1; }
+if x == 80 { x = x + 1; } if x == 81 { x = x + 1; } if x == 82 { x = x + 1; } if x == 83 { x = x + 1; } if x == 84 { x = x + 1; } if x == 85 { x = x + 1; } if x == 86 { x = x + 1; } if x == 87 { x = x + 1; } if x == 88 { x = x + 1; } if x == 89 { x = x + 1; }
+if x == 90 { x = x + 1; } if x == 91 { x = x + 1; } if x == 92 { x = x + 1; } if x == 93 { x = x + 1; } if x == 94 { x = x + 1; } if x == 95 { x = x + 1; } if x == 96 { x = x + 1; } if x == 97 { x = x + 1; } if x == 98 { x = x + 1; } if x == 99 { x = x + 1; }
+printnum x;
+print "\n";
+printnum fact(5);
+print "\n";
@@ -0,0 +1,11 @@
+var x = -3;
+var b = 2;
+
+# -3 * -2 = 6
+print x * -b + 48;
+# wrapping: 51 + -3 = 48
+print 51 + x;
+print 50 - -(-2);
+# `-` binds weaker than `^`, so this is 49 - 4
+print 49 + 4 + -2^2;
+print "\n";
@@ -0,0 +1,8 @@
+var base = 3;
+var exp = 4;
+
+# 3^4 = 81 is computed at runtime, 2^5 is folded at compile time
+print base ^ exp - 2^5;
+print 2 * 3 ^ 2 + 31;
+print base ^ 0 + 49;
+print "\n";
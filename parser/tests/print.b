@@ -1,5 +1,5 @@
-a = 5;
-b = 4;
+var a = 5;
+var b = 4;
 
 print "\"please\nlet\nthis\nbe\non\ndifferent\nlines\n\"";
 
@@ -0,0 +1,13 @@
+var x = 65;
+
+# `print` writes the raw byte, `printnum` the decimal number
+print x;
+print " = ";
+printnum x;
+print "\n";
+
+for var i = 0; i < 12; i = i + 1 {
+    printnum i * 23;
+    print " ";
+}
+print "\n";
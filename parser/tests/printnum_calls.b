@@ -0,0 +1,24 @@
+# `printnum` prints the values returned by (recursive) functions in decimal
+
+fun fib(n) {
+    if n < 2 {
+        return n;
+    }
+    return fib(n - 1) + fib(n - 2);
+}
+
+fun fact(n) {
+    if n == 0 {
+        return 1;
+    }
+    return n * fact(n - 1);
+}
+
+for var i = 0; i < 12; i = i + 1 {
+    printnum fib(i);
+    print " ";
+}
+print "\n";
+var f = fact(5);
+printnum f;
+print "\n";
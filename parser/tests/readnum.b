@@ -0,0 +1,11 @@
+# reads two numbers, one per line, and prints their sum
+print "a: ";
+var a = readnum();
+print "b: ";
+var b = readnum();
+printnum a;
+print " + ";
+printnum b;
+print " = ";
+printnum a + b;
+print "\n";
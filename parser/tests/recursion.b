@@ -0,0 +1,40 @@
+# Recursive functions are compiled to a dispatch loop with a call stack
+
+fun fib(n) {
+    if n < 2 {
+        return n;
+    }
+    return fib(n - 1) + fib(n - 2);
+}
+
+fun fact(n) {
+    if n == 0 {
+        return 1;
+    }
+    return n * fact(n - 1);
+}
+
+fun even(n) {
+    if n == 0 {
+        return 1;
+    }
+    return odd(n - 1);
+}
+
+fun odd(n) {
+    if n == 0 {
+        return 0;
+    }
+    return even(n - 1);
+}
+
+for var i = 0; i < 10; i = i + 1 {
+    print fib(i) + 48;
+    print " ";
+}
+print "\n";
+print fact(4) + 41;
+print "\n";
+print even(7) + 48;
+print odd(7) + 48;
+print "\n";
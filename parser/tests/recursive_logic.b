@@ -0,0 +1,20 @@
+# In a program with recursive functions, the right side of `&&` and `||` is
+# still only evaluated if the left side doesn't decide the result
+
+fun even(n) {
+    return n == 0 || !even(n - 1);
+}
+
+fun shout(x) {
+    print "!";
+    return x;
+}
+
+printnum even(7);
+printnum even(10);
+print "\n";
+
+printnum 0 && shout(1);
+printnum 1 || shout(1);
+printnum 1 && shout(1);
+print "\n";
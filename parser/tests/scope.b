@@ -0,0 +1,28 @@
+# Every `{}` body is a scope. Its variables shadow outer ones and are freed when it ends.
+
+var x = 1;
+if x {
+    var x = 2;
+    printnum x;
+    x = x + 5;
+    printnum x;
+}
+printnum x;
+print "\n";
+
+var total = 0;
+var i = 0;
+while i < 4 {
+    var square = i * i;
+    total = total + square;
+    i = i + 1;
+}
+printnum total;
+print "\n";
+
+for var i = 0; i < 3; i = i + 1 {
+    var s = "ab";
+    print s;
+}
+printnum i;
+print "\n";
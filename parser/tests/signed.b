@@ -0,0 +1,39 @@
+# Signed numbers are stored in two's complement, and compared and divided by their sign
+
+var a: i8 = -1;
+if a < 0 {
+    print "-1 < 0\n";
+}
+var u = 255;
+if u > 0 {
+    print "255 > 0\n";
+}
+
+var b: i8 = -7;
+printnum b / 2;
+print " ";
+printnum b % 2;
+print " ";
+printnum 7 / b;
+print " ";
+printnum b * b;
+print "\n";
+
+var t: i16 = -300;
+var step: i8 = -100;
+t = t + step;
+printnum t;
+print " ";
+printnum t / step;
+print " ";
+printnum -t;
+print "\n";
+
+var lo: i16 = -1000;
+var count = 0;
+while lo < 1000 {
+    lo = lo + 250;
+    count = count + 1;
+}
+printnum count;
+print "\n";
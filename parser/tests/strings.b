@@ -0,0 +1,9 @@
+var greeting = "hello";
+var name = input();
+var line = greeting + ", " + name + "!\n";
+print line;
+printnum len(name);
+print "\n";
+greeting = "hey";
+print greeting + " " + name;
+print '\n';
@@ -0,0 +1,18 @@
+var i = 0;
+while i < 10 {
+    print i + 48;
+    i = i + 1;
+}
+var n = 3;
+while n {
+    var j = n;
+    while j > 0 {
+        print "*";
+        j = j - 1;
+    }
+    print "\n";
+    n = n - 1;
+}
+while 0 {
+    print "never";
+}
@@ -0,0 +1,43 @@
+# Numbers can be declared with more cells, so they don't wrap around at 256
+
+var x: u16 = 1000;
+var y: u16 = 200 + 100;
+printnum x + y;
+print "\n";
+printnum x * 7 - y;
+print "\n";
+printnum x / 7;
+print " ";
+printnum x % 7;
+print "\n";
+
+var big: u32 = 100000;
+big = big * big / 1000;
+printnum big;
+print "\n";
+
+# a counter that goes past 255
+var count: u16 = 0;
+for var i = 0; i < 200; i = i + 1 {
+    count = count + 3;
+}
+printnum count;
+print "\n";
+
+if x > 999 && x <= 1000 && x != y {
+    print "compared\n";
+}
+
+var wrapped: u16 = 0 - 1;
+printnum wrapped;
+print " ";
+printnum -x;
+print "\n";
+
+# expressions are evaluated with as many cells as their widest value or the variable they are assigned to
+var pow: u16 = 2 ^ 10;
+var small: u8 = x;
+printnum pow;
+print " ";
+printnum small;
+print "\n";